@@ -6,6 +6,21 @@ pub fn build_cli() -> App<'static, 'static> {
         .author(clap::crate_authors!())
         .version(clap::crate_version!())
         .setting(AppSettings::ColoredHelp)
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print results as JSON"),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .global(true)
+                .possible_values(&["auto", "terminal", "plain"])
+                .default_value("auto")
+                .help("How to show progress, plain is used if stdout is not a terminal")
+                .value_name("MODE"),
+        )
         .subcommand(
             SubCommand::with_name("list")
                 .about("List installed packages")
@@ -51,7 +66,6 @@ pub fn build_cli() -> App<'static, 'static> {
                         .long("category")
                         .short("c")
                         .help("Install package under provided category")
-                        .default_value("default")
                         .value_name("CATEGORY"),
                 )
                 .arg(
@@ -61,6 +75,25 @@ pub fn build_cli() -> App<'static, 'static> {
                         .value_name("REVISION")
                         .visible_aliases(&["branch", "tag", "commit"])
                 )
+                .arg(
+                    Arg::with_name("locked")
+                        .long("locked")
+                        .conflicts_with("rev")
+                        .help("Checkout the commits recorded in paconfig.lock"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .help("Only fetch this many commits of history")
+                        .value_name("DEPTH"),
+                )
+                .arg(
+                    Arg::with_name("local")
+                        .short("l")
+                        .long("local")
+                        .conflicts_with_all(&["rev", "locked", "depth"])
+                        .help("Install local plugins by path (symlinked, never updated)"),
+                )
                 .arg(
                     Arg::with_name("as")
                         .long("as")
@@ -70,8 +103,8 @@ pub fn build_cli() -> App<'static, 'static> {
                 .arg(
                     Arg::with_name("on")
                         .long("on")
-                        .help("Command for loading the plugins")
-                        .value_name("LOAD_CMD"),
+                        .help("Commands for loading the plugins, comma separated")
+                        .value_name("LOAD_CMDS"),
                 )
                 .arg(
                     Arg::with_name("for")
@@ -79,6 +112,42 @@ pub fn build_cli() -> App<'static, 'static> {
                         .help("Load this plugins for specific types")
                         .value_name("TYPES"),
                 )
+                .arg(
+                    Arg::with_name("on-event")
+                        .long("on-event")
+                        .help("Load this plugins on autocommand events, e.g. InsertEnter")
+                        .value_name("EVENTS"),
+                )
+                .arg(
+                    Arg::with_name("on-map")
+                        .long("on-map")
+                        .help("Load this plugins on key mappings, e.g. <Plug>(foo)")
+                        .value_name("MAPS"),
+                )
+                .arg(
+                    Arg::with_name("on-func")
+                        .long("on-func")
+                        .help("Load this plugins on undefined functions with prefixes, e.g. foo#")
+                        .value_name("PREFIXES"),
+                )
+                .arg(
+                    Arg::with_name("on-path")
+                        .long("on-path")
+                        .help("Load this plugins on reading files matching patterns, e.g. *.foo")
+                        .value_name("PATTERNS"),
+                )
+                .arg(
+                    Arg::with_name("on-require")
+                        .long("on-require")
+                        .help("Load this plugins on requiring Lua modules in Neovim, e.g. foo")
+                        .value_name("MODULES"),
+                )
+                .arg(
+                    Arg::with_name("requires")
+                        .long("requires")
+                        .help("Plugins to install and load before this plugins, comma separated")
+                        .value_name("PLUGINS"),
+                )
                 .arg(
                     Arg::with_name("build")
                         .long("build")
@@ -97,6 +166,17 @@ pub fn build_cli() -> App<'static, 'static> {
         .subcommand(
             SubCommand::with_name("uninstall")
                 .about("Uninstall packages/plugins")
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .help("Uninstall plugins even if other plugins require them"),
+                )
+                .arg(
+                    Arg::with_name("with-orphans")
+                        .long("with-orphans")
+                        .help("Also uninstall the plugins they require which are no longer needed"),
+                )
                 .arg(Arg::with_name("package").required(true).multiple(true)),
         )
         .subcommand(
@@ -136,12 +216,179 @@ pub fn build_cli() -> App<'static, 'static> {
                         .long("threads")
                         .help("Updating packages concurrently"),
                 )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .conflicts_with("stash")
+                        .help("Discard local modifications of packages"),
+                )
+                .arg(
+                    Arg::with_name("stash")
+                        .long("stash")
+                        .help("Stash local modifications of packages before updating"),
+                )
+                .arg(
+                    Arg::with_name("log")
+                        .short("l")
+                        .long("log")
+                        .help("Show the new commits of updated packages"),
+                )
+                .arg(
+                    Arg::with_name("log-format")
+                        .long("log-format")
+                        .help("Format of the commits shown by --log (%H, %h, %s, %an, %ae)")
+                        .default_value("%h %s")
+                        .value_name("FORMAT"),
+                )
                 .arg(
                     Arg::with_name("package")
                         .help("Packages to update, default all")
                         .multiple(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Show local changes and checkout state of packages")
+                .arg(
+                    Arg::with_name("verbose")
+                        .short("v")
+                        .long("verbose")
+                        .help("List modified files"),
+                )
+                .arg(
+                    Arg::with_name("package")
+                        .help("Packages to show, default all")
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("outdated")
+                .about("List packages behind their remote, without updating them")
+                .arg(
+                    Arg::with_name("threads")
+                        .short("j")
+                        .long("threads")
+                        .help("Checking packages concurrently")
+                        .value_name("THREADS"),
+                )
+                .arg(
+                    Arg::with_name("package")
+                        .help("Packages to check, default all")
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Rollback packages to a snapshot taken before an update")
+                .arg(
+                    Arg::with_name("list")
+                        .short("l")
+                        .long("list")
+                        .conflicts_with("snapshot")
+                        .help("List saved snapshots"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("j")
+                        .long("threads")
+                        .help("Restoring packages concurrently")
+                        .value_name("THREADS"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .conflicts_with("stash")
+                        .help("Discard local modifications of packages"),
+                )
+                .arg(
+                    Arg::with_name("stash")
+                        .long("stash")
+                        .help("Stash local modifications of packages before restoring"),
+                )
+                .arg(
+                    Arg::with_name("snapshot")
+                        .help("Snapshot to restore, default latest")
+                        .value_name("SNAPSHOT"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("Remove detached packages and empty directories under pack/")
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only show what would be removed"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Do not ask for confirmation"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("adopt")
+                .about("Add existing package directories to paconfig")
+                .arg(
+                    Arg::with_name("path")
+                        .multiple(true)
+                        .help("Package directories to adopt, every detached one by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Install the plugins declared for another plugin manager")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .short("f")
+                        .required(true)
+                        .possible_values(&["vim-plug", "vundle", "dein", "lazy"])
+                        .help("Plugin manager the file is written for")
+                        .value_name("MANAGER"),
+                )
+                .arg(
+                    Arg::with_name("category")
+                        .long("category")
+                        .short("c")
+                        .help("Install packages under provided category")
+                        .value_name("CATEGORY"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .short("j")
+                        .long("threads")
+                        .help("Installing packages concurrently")
+                        .value_name("THREADS"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only show the packages that would be installed"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .help("vimrc or Lua file declaring the plugins"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Print paconfig in the syntax of another plugin manager")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .short("f")
+                        .possible_values(&["vim-plug", "lazy", "txt"])
+                        .default_value("txt")
+                        .help("vim-plug block, lazy.nvim spec or one plugin per line")
+                        .value_name("FORMAT"),
+                ),
+        )
         // TODO: remove generate subcommand (package config no longer managed by pac)
         .subcommand(
             SubCommand::with_name("generate")
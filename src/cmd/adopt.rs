@@ -0,0 +1,121 @@
+use crate::git;
+use crate::package::{self, Package};
+use crate::{Error, Result};
+
+use clap::ArgMatches;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug)]
+struct AdoptArgs {
+    paths: Vec<String>,
+}
+
+impl AdoptArgs {
+    fn from_matches(m: &ArgMatches) -> AdoptArgs {
+        AdoptArgs {
+            paths: m.values_of_lossy("path").unwrap_or_default(),
+        }
+    }
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = AdoptArgs::from_matches(matches);
+
+    if let Err(e) = adopt(&args.paths) {
+        die!("Err: {}", e);
+    }
+}
+
+/// Add the given package directories, or every detached one, to paconfig.
+fn adopt(paths: &[String]) -> Result<()> {
+    let mut packs = package::fetch()?;
+    let paths = if paths.is_empty() {
+        package::detached_paths(&packs)?
+    } else {
+        paths.iter().map(PathBuf::from).collect()
+    };
+    if paths.is_empty() {
+        println!("Nothing to adopt");
+        return Ok(());
+    }
+
+    let mut adopted = false;
+    for path in paths {
+        match adopt_path(&path) {
+            Ok(pack) => {
+                if packs.iter().any(|p| p.idname == pack.idname) {
+                    eprintln!(
+                        "Skip {}: {} is already in paconfig",
+                        path.display(),
+                        pack.idname
+                    );
+                    continue;
+                }
+                println!("Adopted {} from {}", pack, path.display());
+                packs.push(pack);
+                adopted = true;
+            }
+            Err(e) => eprintln!("Fail to adopt {}: {}", path.display(), e),
+        }
+    }
+    if !adopted {
+        return Ok(());
+    }
+
+    packs.sort_by(|a, b| a.idname.cmp(&b.idname));
+    package::update_pac_plugin(&packs)?;
+    package::save_lock(&packs)?;
+    package::save(packs)
+}
+
+/// Build the package of a directory under `pack`. Symlinks are adopted as
+/// local plugins, other directories must be git repos with an `origin`.
+fn adopt_path(path: &Path) -> Result<Package> {
+    let (category, option, name) = pack_location(path)?;
+    let path = package::pack_path(&category, &option, &name);
+    let opt = option == "opt";
+
+    if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+        let target = fs::canonicalize(&path)?;
+        let mut pack = Package::new(&name, &target.to_string_lossy(), &category, opt);
+        pack.local = true;
+        return Ok(pack);
+    }
+
+    let (remote, revision) = git::origin_info(&path)?;
+    let mut pack = Package::new(&name, &remote, &category, opt);
+    pack.revision = revision;
+    Ok(pack)
+}
+
+/// Split a path of the form `pack/<category>/<start|opt>/<name>` into its
+/// category, option and name. The last component is not resolved, so that
+/// symlinked plugins are found at their location rather than their target.
+fn pack_location(path: &Path) -> Result<(String, String, String)> {
+    let not_in_pack = || Error::not_a_package(path);
+    let name = path.file_name().ok_or_else(not_in_pack)?;
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => fs::canonicalize(p)?,
+        _ => fs::canonicalize(".")?,
+    };
+    let pack_dir = fs::canonicalize(package::pack_dir())?;
+    let rel = parent.strip_prefix(&pack_dir).map_err(|_| not_in_pack())?;
+
+    let parts = rel
+        .components()
+        .map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(not_in_pack)?;
+    match parts.as_slice() {
+        [category, option] if option == "start" || option == "opt" => Ok((
+            category.clone(),
+            option.clone(),
+            name.to_string_lossy().into_owned(),
+        )),
+        _ => Err(not_in_pack()),
+    }
+}
@@ -0,0 +1,151 @@
+use crate::package;
+use crate::Result;
+
+use clap::ArgMatches;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+struct CleanArgs {
+    dry_run: bool,
+    yes: bool,
+}
+
+impl CleanArgs {
+    fn from_matches(m: &ArgMatches) -> CleanArgs {
+        CleanArgs {
+            dry_run: m.is_present("dry-run"),
+            yes: m.is_present("yes"),
+        }
+    }
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = CleanArgs::from_matches(matches);
+
+    if let Err(e) = clean(args.dry_run, args.yes) {
+        die!("Err: {}", e);
+    }
+}
+
+/// Remove the package directories not in paconfig, then the directories
+/// left empty, and regenerate `_pac.vim` if it has stale entries.
+fn clean(dry_run: bool, yes: bool) -> Result<()> {
+    let packs = package::fetch()?;
+    let detached = package::detached_paths(&packs)?;
+    let empty = empty_dirs(package::pack_dir(), &detached)?;
+
+    let pac_plugin = package::pac_plugin_path();
+    let stale = package::pac_plugin_stale(&packs)?;
+
+    if detached.is_empty() && empty.is_empty() && !stale {
+        println!("Nothing to clean");
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Remove" };
+    for path in detached.iter().chain(empty.iter()) {
+        println!("{} {}", verb, path.display());
+    }
+    if stale {
+        let verb = if dry_run {
+            "Would regenerate"
+        } else {
+            "Regenerate"
+        };
+        println!("{} {}", verb, pac_plugin.display());
+    }
+    if dry_run || (!yes && !confirm("Proceed?")?) {
+        return Ok(());
+    }
+
+    for path in &detached {
+        // detached symlinks are removed without touching their target
+        if fs::symlink_metadata(path)?.file_type().is_symlink() {
+            fs::remove_file(path)?;
+        } else {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    // deepest first, so option dirs are removed before their category
+    for path in empty.iter().rev() {
+        fs::remove_dir(path)?;
+    }
+    if stale {
+        package::update_pac_plugin(&packs)?;
+    }
+    Ok(())
+}
+
+/// Option (`start`/`opt`) and category directories under `pack_dir` that
+/// are empty, or would be once `removed` is deleted. Parents come before
+/// their children.
+fn empty_dirs(pack_dir: &Path, removed: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    // every entry of `dir` is in `gone`
+    let is_empty = |dir: &Path, gone: &[PathBuf]| -> Result<bool> {
+        for e in dir.read_dir()? {
+            if !gone.contains(&e?.path()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    };
+
+    let mut empty = Vec::new();
+    if !pack_dir.is_dir() {
+        return Ok(empty);
+    }
+    for cate in pack_dir.read_dir()? {
+        let cate = cate?.path();
+        if !cate.is_dir() || fs::symlink_metadata(&cate)?.file_type().is_symlink() {
+            continue;
+        }
+        let mut gone = removed.to_vec();
+        let mut options = Vec::new();
+        for option in ["start", "opt"].iter().map(|o| cate.join(o)) {
+            if option.is_dir() && is_empty(&option, removed)? {
+                gone.push(option.clone());
+                options.push(option);
+            }
+        }
+        if is_empty(&cate, &gone)? {
+            empty.push(cate);
+        }
+        empty.extend(options);
+    }
+    empty.sort();
+    Ok(empty)
+}
+
+/// Ask a yes/no question on stdin, defaulting to no.
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_dirs_after_removal() {
+        let pack = std::env::temp_dir().join(format!("pac-clean-{}", std::process::id()));
+        for dir in ["a/start/x", "a/opt/y", "b/start/z", "c/opt"].iter() {
+            fs::create_dir_all(pack.join(dir)).unwrap();
+        }
+
+        let removed = vec![pack.join("a/start/x"), pack.join("b/start/z")];
+        let empty = empty_dirs(&pack, &removed).unwrap();
+        fs::remove_dir_all(&pack).unwrap();
+
+        let expected = ["a/start", "b", "b/start", "c", "c/opt"]
+            .iter()
+            .map(|d| pack.join(d))
+            .collect::<Vec<_>>();
+        assert_eq!(empty, expected);
+    }
+}
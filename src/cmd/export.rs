@@ -0,0 +1,293 @@
+use crate::git;
+use crate::package::{self, Package};
+use crate::utils::lua_string;
+
+use clap::ArgMatches;
+
+#[derive(Debug)]
+struct ExportArgs {
+    format: String,
+}
+
+impl ExportArgs {
+    fn from_matches(m: &ArgMatches) -> ExportArgs {
+        ExportArgs {
+            format: m.value_of("format").unwrap_or("txt").to_string(),
+        }
+    }
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = ExportArgs::from_matches(matches);
+
+    let packs = match package::fetch() {
+        Ok(p) => p,
+        Err(e) => die!("Err: {}", e),
+    };
+    // only installed plugins tell tags and branches apart
+    let tags = packs
+        .iter()
+        .filter(|p| matches!(p.revision, Some(ref r) if git::is_tag(&p.path(), r)))
+        .map(|p| p.idname.clone())
+        .collect::<Vec<_>>();
+    let output = match args.format.as_str() {
+        "vim-plug" => vim_plug(&packs, &tags),
+        "lazy" => lazy(&packs, &tags),
+        _ => txt(&packs),
+    };
+    print!("{}", output);
+}
+
+/// `user/repo` for GitHub, otherwise the remote or path of a local plugin
+fn source(pack: &Package) -> &str {
+    if pack.on_github() {
+        &pack.idname
+    } else {
+        &pack.remote
+    }
+}
+
+/// Other managers tell commits, branches and tags apart. `tags` are the
+/// idnames of packages whose revision is a tag.
+fn rev_key(pack: &Package, rev: &str, tags: &[String]) -> &'static str {
+    if tags.contains(&pack.idname) {
+        "tag"
+    } else if rev.len() >= 7 && rev.len() <= 40 && rev.chars().all(|c| c.is_ascii_hexdigit()) {
+        "commit"
+    } else {
+        "branch"
+    }
+}
+
+/// Warn about the triggers of a package that `format` cannot express. The
+/// package is then loaded at startup rather than never.
+fn warn_dropped(pack: &Package, format: &str, triggers: &[(&str, &[String])]) {
+    for (kind, dropped) in triggers.iter().filter(|(_, t)| !t.is_empty()) {
+        eprintln!(
+            "Warn: {} cannot load {} on {} {}",
+            format,
+            pack.idname,
+            kind,
+            dropped.join(", ")
+        );
+    }
+}
+
+/// One plugin per line, as accepted by `pac install`
+fn txt(packs: &[Package]) -> String {
+    packs.iter().map(|p| format!("{}\n", source(p))).collect()
+}
+
+fn vim_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn vim_list(items: &[String]) -> String {
+    let items = items.iter().map(|s| vim_string(s)).collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+/// A `call plug#begin()` block with one `Plug` per package
+fn vim_plug(packs: &[Package], tags: &[String]) -> String {
+    let mut out = String::from("call plug#begin()\n");
+    for pack in packs {
+        let mut opts = Vec::new();
+        if !pack.for_types.is_empty() {
+            opts.push(format!("'for': {}", vim_list(&pack.for_types)));
+        }
+        // vim-plug loads on commands and <Plug> mappings only
+        let (maps, other_maps): (Vec<_>, Vec<_>) = pack
+            .on_map
+            .iter()
+            .cloned()
+            .partition(|m| m.starts_with("<Plug>"));
+        let on = pack
+            .load_commands
+            .iter()
+            .cloned()
+            .chain(maps)
+            .collect::<Vec<_>>();
+        let dropped = [
+            ("events", &pack.on_event[..]),
+            ("mappings", &other_maps[..]),
+            ("functions", &pack.on_func[..]),
+            ("paths", &pack.on_path[..]),
+            ("Lua modules", &pack.on_require[..]),
+        ];
+        warn_dropped(pack, "vim-plug", &dropped);
+        let eager = dropped.iter().any(|(_, t)| !t.is_empty());
+        match on.as_slice() {
+            [] if pack.opt && pack.for_types.is_empty() && !eager => {
+                // loaded by `packadd` only, vim-plug does the same with no commands
+                opts.push("'on': []".to_string())
+            }
+            [] => (),
+            [c] => opts.push(format!("'on': {}", vim_string(c))),
+            _ => opts.push(format!("'on': {}", vim_list(&on))),
+        }
+        if let Some(ref b) = pack.build_command {
+            opts.push(format!("'do': {}", vim_string(b)));
+        }
+        if let Some(ref r) = pack.revision {
+            opts.push(format!("'{}': {}", rev_key(pack, r, tags), vim_string(r)));
+        }
+        if pack.name != Package::name_from_remote(&pack.remote) {
+            opts.push(format!("'as': {}", vim_string(&pack.name)));
+        }
+
+        out.push_str(&format!("Plug {}", vim_string(source(pack))));
+        if !opts.is_empty() {
+            out.push_str(&format!(", {{ {} }}", opts.join(", ")));
+        }
+        out.push('\n');
+    }
+    out.push_str("call plug#end()\n");
+    out
+}
+
+fn lua_list(items: &[String]) -> String {
+    let items = items.iter().map(|s| lua_string(s)).collect::<Vec<_>>();
+    format!("{{ {} }}", items.join(", "))
+}
+
+/// A lazy.nvim spec returning one table per package
+fn lazy(packs: &[Package], tags: &[String]) -> String {
+    let mut out = String::from("return {\n");
+    for pack in packs {
+        let mut fields = vec![if pack.local {
+            format!("dir = {}", lua_string(&pack.remote))
+        } else if pack.on_github() {
+            lua_string(&pack.idname)
+        } else {
+            format!("url = {}", lua_string(&pack.remote))
+        }];
+        if pack.name != Package::name_from_remote(&pack.remote) {
+            fields.push(format!("name = {}", lua_string(&pack.name)));
+        }
+        if !pack.for_types.is_empty() {
+            fields.push(format!("ft = {}", lua_list(&pack.for_types)));
+        }
+        match pack.load_commands.as_slice() {
+            [] => (),
+            [c] => fields.push(format!("cmd = {}", lua_string(c))),
+            cmds => fields.push(format!("cmd = {}", lua_list(cmds))),
+        }
+        // lazy.nvim takes events with a pattern, e.g. `BufRead *.rs`
+        let events = pack
+            .on_event
+            .iter()
+            .cloned()
+            .chain(pack.on_path.iter().map(|p| format!("BufRead {}", p)))
+            .collect::<Vec<_>>();
+        if !events.is_empty() {
+            fields.push(format!("event = {}", lua_list(&events)));
+        }
+        if !pack.on_map.is_empty() {
+            fields.push(format!("keys = {}", lua_list(&pack.on_map)));
+        }
+        let triggered = !pack.for_types.is_empty()
+            || !pack.load_commands.is_empty()
+            || !events.is_empty()
+            || !pack.on_map.is_empty();
+        warn_dropped(pack, "lazy.nvim", &[("functions", &pack.on_func)]);
+        if pack.opt && !triggered && pack.on_func.is_empty() {
+            // loaded by `packadd` or `require` only
+            fields.push("lazy = true".to_string());
+        }
+        if !pack.requires.is_empty() {
+            // as lazy.nvim finds them, from the packages they refer to
+            let deps = pack
+                .required_idnames()
+                .iter()
+                .zip(&pack.requires)
+                .map(
+                    |(idname, req)| match packs.iter().find(|p| &p.idname == idname) {
+                        Some(p) => source(p).to_string(),
+                        None => req.clone(),
+                    },
+                )
+                .collect::<Vec<_>>();
+            fields.push(format!("dependencies = {}", lua_list(&deps)));
+        }
+        if let Some(ref b) = pack.build_command {
+            fields.push(format!("build = {}", lua_string(b)));
+        }
+        if let Some(ref r) = pack.revision {
+            fields.push(format!("{} = {}", rev_key(pack, r, tags), lua_string(r)));
+        }
+        out.push_str(&format!("  {{ {} }},\n", fields.join(", ")));
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packs() -> Vec<Package> {
+        let mut fugitive = Package::new(
+            "vim-fugitive",
+            "https://github.com/tpope/vim-fugitive",
+            "default",
+            true,
+        );
+        fugitive.load_commands = vec!["Git".to_string()];
+        fugitive.revision = Some("v3.7".to_string());
+        fugitive.on_map = vec!["<Plug>fugitive:".to_string(), "<Leader>g".to_string()];
+        fugitive.on_path = vec!["COMMIT_EDITMSG".to_string()];
+
+        let mut sandwich = Package::new(
+            "sandwich",
+            "https://gitlab.com/machakann/vim-sandwich",
+            "default",
+            true,
+        );
+        sandwich.for_types = vec!["python".to_string(), "rust".to_string()];
+        sandwich.build_command = Some("make 'all'".to_string());
+        sandwich.requires = vec!["tpope/vim-fugitive".to_string()];
+
+        let mut local = Package::new("mine", "/home/me/mine", "default", false);
+        local.local = true;
+
+        let mut events = Package::new("events", "https://github.com/u/events", "default", true);
+        events.on_event = vec!["InsertEnter".to_string()];
+        events.on_func = vec!["events#".to_string()];
+        events.revision = Some("main".to_string());
+        vec![fugitive, sandwich, local, events]
+    }
+
+    fn tags() -> Vec<String> {
+        vec!["tpope/vim-fugitive".to_string()]
+    }
+
+    #[test]
+    fn export_vim_plug() {
+        assert_eq!(
+            vim_plug(&packs(), &tags()),
+            "call plug#begin()\n\
+             Plug 'tpope/vim-fugitive', { 'on': ['Git', '<Plug>fugitive:'], 'tag': 'v3.7' }\n\
+             Plug 'https://gitlab.com/machakann/vim-sandwich', \
+             { 'for': ['python', 'rust'], 'do': 'make ''all''', 'as': 'sandwich' }\n\
+             Plug '/home/me/mine'\n\
+             Plug 'u/events', { 'branch': 'main' }\n\
+             call plug#end()\n"
+        );
+    }
+
+    #[test]
+    fn export_lazy() {
+        assert_eq!(
+            lazy(&packs(), &tags()),
+            "return {\n  \
+             { \"tpope/vim-fugitive\", cmd = \"Git\", event = { \"BufRead COMMIT_EDITMSG\" }, \
+             keys = { \"<Plug>fugitive:\", \"<Leader>g\" }, tag = \"v3.7\" },\n  \
+             { url = \"https://gitlab.com/machakann/vim-sandwich\", name = \"sandwich\", \
+             ft = { \"python\", \"rust\" }, dependencies = { \"tpope/vim-fugitive\" }, \
+             build = \"make 'all'\" },\n  \
+             { dir = \"/home/me/mine\" },\n  \
+             { \"u/events\", event = { \"InsertEnter\" }, branch = \"main\" },\n\
+             }\n"
+        );
+    }
+}
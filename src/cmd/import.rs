@@ -0,0 +1,946 @@
+use crate::cmd::install;
+use crate::package::{self, Package, Settings};
+use crate::report::Progress;
+
+use clap::{value_t, ArgMatches};
+use std::fs;
+
+#[derive(Debug)]
+struct ImportArgs {
+    file: String,
+    from: Format,
+    category: String,
+    threads: Option<usize>,
+    progress: Progress,
+    dry_run: bool,
+    json: bool,
+}
+
+impl ImportArgs {
+    fn from_matches(m: &ArgMatches) -> ImportArgs {
+        ImportArgs {
+            file: value_t!(m, "file", String).unwrap(),
+            from: match m.value_of("from") {
+                Some("vim-plug") => Format::VimPlug,
+                Some("vundle") => Format::Vundle,
+                Some("dein") => Format::Dein,
+                _ => Format::Lazy,
+            },
+            category: value_t!(m, "category", String)
+                .unwrap_or_else(|_| package::settings().category.clone()),
+            threads: value_t!(m, "threads", usize).ok(),
+            progress: Progress::from_matches(m),
+            dry_run: m.is_present("dry-run"),
+            json: m.is_present("json"),
+        }
+    }
+}
+
+/// Plugin managers whose plugin lists can be imported
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    /// `Plug 'user/repo', {...}`
+    VimPlug,
+    /// `Plugin 'user/repo', {...}`
+    Vundle,
+    /// `call dein#add('user/repo', {...})`
+    Dein,
+    /// lazy.nvim (or packer) Lua tables `{ "user/repo", ... }`
+    Lazy,
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = ImportArgs::from_matches(matches);
+
+    let threads = args
+        .threads
+        .unwrap_or_else(|| package::settings().threads());
+    if threads < 1 {
+        die_json!(args.json, "Threads should be greater than 0");
+    }
+
+    let content = match fs::read_to_string(&args.file) {
+        Ok(c) => c,
+        Err(e) => die_json!(args.json, "Fail to read {}: {}", args.file, e),
+    };
+    let specs = match args.from {
+        Format::Lazy => parse_lua(&content),
+        format => parse_vim(&content, format),
+    };
+    if specs.is_empty() {
+        die_json!(args.json, "No plugins found in {}", args.file);
+    }
+
+    let packs = merge_specs(specs, package::settings())
+        .into_iter()
+        .map(|spec| spec.into_package(&args.category))
+        .collect::<Vec<_>>();
+
+    if args.dry_run {
+        for pack in &packs {
+            println!("{}", pack);
+        }
+        return;
+    }
+    if let Err(e) = install::install_packages(packs, threads, args.progress, args.json) {
+        die_json!(args.json, "Err: {}", e);
+    }
+}
+
+/// A plugin as declared for another plugin manager
+#[derive(Debug, Default, PartialEq)]
+struct Spec {
+    /// `user/repo`, url or path of a local plugin
+    plugin: String,
+    local: bool,
+    name: Option<String>,
+    for_types: Vec<String>,
+    on: Vec<String>,
+    on_event: Vec<String>,
+    on_map: Vec<String>,
+    on_func: Vec<String>,
+    on_path: Vec<String>,
+    on_require: Vec<String>,
+    requires: Vec<String>,
+    build: Option<String>,
+    rev: Option<String>,
+    opt: bool,
+}
+
+impl Spec {
+    fn new(plugin: &str) -> Spec {
+        Spec {
+            plugin: plugin.to_string(),
+            // vim-plug takes local plugins as paths, lazy.nvim with `dir`
+            local: plugin.starts_with('/') || plugin.starts_with('~'),
+            ..Spec::default()
+        }
+    }
+
+    /// Apply an option of the spec, with the keys of the given manager.
+    fn set(&mut self, format: Format, key: &str, value: &Value) {
+        use Format::*;
+        match (format, key) {
+            (VimPlug, "for") | (Dein, "on_ft") | (Lazy, "ft") => {
+                self.for_types = value.strings();
+                self.opt = true;
+            }
+            // vim-plug loads on <Plug> mappings as well as commands
+            (VimPlug, "on") => {
+                let (maps, cmds) = value
+                    .strings()
+                    .into_iter()
+                    .partition(|c| c.starts_with("<Plug>"));
+                self.on = cmds;
+                self.on_map = maps;
+                self.opt = true;
+            }
+            (Dein, "on_cmd") | (Lazy, "cmd") => {
+                self.on = value.strings();
+                self.opt = true;
+            }
+            (Dein, "on_event") => {
+                self.on_event = value.strings();
+                self.opt = true;
+            }
+            (Lazy, "event") => {
+                self.on_event = lazy_events(&self.plugin, value.strings());
+                self.opt = true;
+            }
+            (Dein, "on_map") => {
+                self.on_map = value.strings();
+                self.opt = true;
+            }
+            // tables of lazy.nvim keys start with the lhs
+            (Lazy, "keys") => {
+                self.on_map = match value {
+                    Value::Table(items, _) => items
+                        .iter()
+                        .filter_map(|k| match k {
+                            Value::Table(items, _) => items.first().and_then(|v| v.string()),
+                            k => k.string(),
+                        })
+                        .collect(),
+                    v => v.strings(),
+                };
+                self.opt = true;
+            }
+            (Dein, "on_func") => {
+                self.on_func = value.strings();
+                self.opt = true;
+            }
+            (Dein, "depends") => self.requires = value.strings(),
+            (Dein, "on_path") => {
+                self.on_path = value.strings();
+                self.opt = true;
+            }
+            // packer's `module`
+            (Dein, "on_lua") | (Lazy, "module") => {
+                self.on_require = value.strings();
+                self.opt = true;
+            }
+            (Dein, "lazy") | (Lazy, "lazy") => self.opt = self.opt || value.is_true(),
+            // Vim commands (e.g. `:TSUpdate`) and functions cannot be run by pac
+            (VimPlug, "do") | (Dein, "build") | (Lazy, "build") => match value {
+                Value::Str(b) if !b.starts_with(':') => self.build = Some(b.clone()),
+                Value::Str(b) => eprintln!(
+                    "Skip build of {}: Vim command {} cannot be run by pac",
+                    self.plugin, b
+                ),
+                _ => eprintln!(
+                    "Skip build of {}: only shell commands can be run by pac",
+                    self.plugin
+                ),
+            },
+            (VimPlug, "branch")
+            | (VimPlug, "tag")
+            | (VimPlug, "commit")
+            | (Dein, "rev")
+            | (Lazy, "branch")
+            | (Lazy, "tag")
+            | (Lazy, "commit") => self.rev = value.string(),
+            (VimPlug, "as") | (Vundle, "name") | (Dein, "name") | (Lazy, "name") => {
+                self.name = value.string()
+            }
+            (Lazy, "url") => self.plugin = value.string().unwrap_or_default(),
+            (Lazy, "dir") => {
+                self.plugin = value.string().unwrap_or_default();
+                self.local = true;
+            }
+            _ => (),
+        }
+    }
+
+    fn remote(&self, settings: &Settings) -> String {
+        if self.local {
+            self.plugin.clone()
+        } else {
+            settings.expand_remote(&self.plugin)
+        }
+    }
+
+    /// Add the options of another spec of the same plugin, the ones of the
+    /// later spec taking precedence like in lazy.nvim.
+    fn merge(&mut self, other: Spec) {
+        fn extend(list: &mut Vec<String>, other: Vec<String>) {
+            for e in other {
+                if !list.contains(&e) {
+                    list.push(e);
+                }
+            }
+        }
+        extend(&mut self.for_types, other.for_types);
+        extend(&mut self.on, other.on);
+        extend(&mut self.on_event, other.on_event);
+        extend(&mut self.on_map, other.on_map);
+        extend(&mut self.on_func, other.on_func);
+        extend(&mut self.on_path, other.on_path);
+        extend(&mut self.on_require, other.on_require);
+        extend(&mut self.requires, other.requires);
+        self.name = other.name.or(self.name.take());
+        self.build = other.build.or(self.build.take());
+        self.rev = other.rev.or(self.rev.take());
+        self.opt = self.opt || other.opt;
+    }
+
+    fn into_package(self, category: &str) -> Package {
+        let remote = self.remote(package::settings());
+        let name = self
+            .name
+            .unwrap_or_else(|| Package::name_from_remote(&remote));
+
+        let mut pack = Package::new(&name, &remote, category, self.opt);
+        pack.local = self.local;
+        pack.revision = self.rev;
+        pack.for_types = self.for_types;
+        pack.load_commands = self.on;
+        pack.on_event = self.on_event;
+        pack.on_map = self.on_map;
+        pack.on_func = self.on_func;
+        pack.on_path = self.on_path;
+        pack.on_require = self.on_require;
+        pack.requires = self.requires;
+        pack.build_command = self.build;
+        pack
+    }
+}
+
+/// Merge the specs of the same plugin, e.g. a bare `dependencies` entry and
+/// the full spec of the plugin, keeping the order of their first appearance.
+fn merge_specs(specs: Vec<Spec>, settings: &Settings) -> Vec<Spec> {
+    let mut merged: Vec<(String, Spec)> = Vec::new();
+    for spec in specs {
+        let idname = Package::idname_from_remote(&spec.remote(settings));
+        match merged.iter_mut().find(|(i, _)| *i == idname) {
+            Some((_, s)) => s.merge(spec),
+            None => merged.push((idname, spec)),
+        }
+    }
+    merged.into_iter().map(|(_, s)| s).collect()
+}
+
+/// Replace the events lazy.nvim and LazyVim fire themselves with the Vim
+/// events they stand for. Other such events are dropped with a warning.
+fn lazy_events(plugin: &str, events: Vec<String>) -> Vec<String> {
+    let mut ret = Vec::new();
+    for e in events {
+        match e.as_str() {
+            "VeryLazy" => ret.push("VimEnter".to_string()),
+            "LazyFile" => ret.extend(
+                ["BufReadPost", "BufNewFile", "BufWritePre"]
+                    .iter()
+                    .map(|e| e.to_string()),
+            ),
+            e if e.starts_with("Lazy") => {
+                eprintln!(
+                    "Skip event {} of {}: it is only fired by lazy.nvim",
+                    e, plugin
+                )
+            }
+            _ => ret.push(e),
+        }
+    }
+    ret
+}
+
+/// Parse the `Plug`, `Plugin` or `call dein#add` lines of a vimrc.
+fn parse_vim(content: &str, format: Format) -> Vec<Spec> {
+    // join continuation lines, which start with a backslash
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        match (trimmed.strip_prefix('\\'), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(trimmed.to_string()),
+        }
+    }
+
+    let mut specs = Vec::new();
+    for line in lines.iter().filter(|l| !l.starts_with('"')) {
+        let mut parser = Parser::new(line, false);
+        // commands can be chained with bars: Plug 'a' | Plug 'b'
+        loop {
+            let command = parser.word();
+            let args = match (format, command.as_str()) {
+                (Format::VimPlug, "Plug") | (Format::Vundle, "Plugin") => parser.args(None),
+                (Format::Dein, "call") if parser.word() == "dein#add" => parser.args(Some(')')),
+                _ => Vec::new(),
+            };
+            if let Some(Value::Str(plugin)) = args.first() {
+                let mut spec = Spec::new(plugin);
+                if let Some(Value::Table(_, fields)) = args.get(1) {
+                    for (key, value) in fields {
+                        spec.set(format, key, value);
+                    }
+                }
+                specs.push(spec);
+            }
+            if !parser.skip_past('|') {
+                break;
+            }
+        }
+    }
+    specs
+}
+
+/// Collect the plugin specs of the Lua tables of a lazy.nvim (or packer)
+/// config: tables whose only item is a `user/repo` string, and such strings
+/// in lists of specs and `dependencies`.
+fn parse_lua(content: &str) -> Vec<Spec> {
+    let mut parser = Parser::new(content, true);
+    let mut specs = Vec::new();
+    while parser.next_table() {
+        collect_lua(&parser.value(), &mut specs);
+    }
+    specs
+}
+
+fn collect_lua(value: &Value, specs: &mut Vec<Spec>) {
+    let is_repo = |s: &str| s.matches('/').count() == 1 && !s.contains(char::is_whitespace);
+    match value {
+        Value::Str(s) if is_repo(s) => specs.push(Spec::new(s)),
+        Value::Table(items, fields) => {
+            // a spec has a single item, otherwise it is a list of specs
+            let is_spec = match items.as_slice() {
+                [Value::Str(s)] => is_repo(s),
+                [] => fields.iter().any(|(k, _)| k == "url" || k == "dir"),
+                _ => false,
+            };
+            if !is_spec {
+                items.iter().for_each(|v| collect_lua(v, specs));
+                return;
+            }
+            let mut spec = match items.first() {
+                Some(Value::Str(s)) => Spec::new(s),
+                _ => Spec::default(),
+            };
+            for (key, value) in fields {
+                spec.set(Format::Lazy, key, value);
+            }
+            let mut deps = Vec::new();
+            for (_, value) in fields.iter().filter(|(k, _)| k == "dependencies") {
+                match value {
+                    Value::Table(items, _) => items.iter().for_each(|v| collect_lua(v, &mut deps)),
+                    v => collect_lua(v, &mut deps),
+                }
+            }
+            // nested dependencies are required by the dependencies themselves
+            let nested = deps
+                .iter()
+                .flat_map(|d| d.requires.clone())
+                .collect::<Vec<_>>();
+            spec.requires = deps
+                .iter()
+                .map(|d| d.plugin.clone())
+                .filter(|p| !nested.contains(p))
+                .collect();
+            specs.push(spec);
+            specs.extend(deps);
+        }
+        _ => (),
+    }
+}
+
+/// A literal of Vimscript or Lua. Vim lists and dicts, and Lua tables are
+/// all tables of items and keyed fields.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Table(Vec<Value>, Vec<(String, Value)>),
+    /// Any other expression, e.g. a number, boolean or function
+    Other(String),
+}
+
+impl Value {
+    fn string(&self) -> Option<String> {
+        match self {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// A string split on commas, or the strings of a list.
+    fn strings(&self) -> Vec<String> {
+        match self {
+            Value::Str(s) => s
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            Value::Table(items, _) => items.iter().flat_map(|v| v.strings()).collect(),
+            Value::Other(_) => Vec::new(),
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        matches!(self, Value::Other(s) if s == "1" || s == "true" || s == "v:true")
+    }
+}
+
+/// Minimal parser of Vimscript and Lua literals, skipping over anything it
+/// does not understand.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    lua: bool,
+}
+
+impl Parser {
+    fn new(s: &str, lua: bool) -> Parser {
+        Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+            lua,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    /// Skip whitespace, and comments in Lua.
+    fn skip_ws(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => self.pos += 1,
+                Some('-') if self.lua && self.peek_at(1) == Some('-') => {
+                    self.pos += 2;
+                    if self.peek() == Some('[') && self.long_bracket().is_some() {
+                        continue;
+                    }
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Read an identifier such as `Plug`, `dein#add`, `v:true` or `vim.fn`.
+    fn word(&mut self) -> String {
+        self.skip_ws();
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || "_#:.".contains(c))
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Move past the next `target` outside of strings and nested brackets.
+    /// Returns false if there is none.
+    fn skip_past(&mut self, target: char) -> bool {
+        let mut depth = 0usize;
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                None => return false,
+                Some(c) if c == target && depth == 0 => {
+                    self.pos += 1;
+                    return true;
+                }
+                Some('\'') | Some('"') => {
+                    self.string();
+                }
+                Some('(') | Some('[') | Some('{') => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(')') | Some(']') | Some('}') => {
+                    depth = depth.saturating_sub(1);
+                    self.pos += 1;
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Move to the next `{` outside of strings and comments. Returns false
+    /// if there is none.
+    fn next_table(&mut self) -> bool {
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                None => return false,
+                Some('{') => return true,
+                Some('\'') | Some('"') => {
+                    self.string();
+                }
+                Some('[') if self.long_bracket().is_some() => (),
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Parse comma separated arguments up to `close`, or the end of input.
+    fn args(&mut self, close: Option<char>) -> Vec<Value> {
+        self.skip_ws();
+        if close.is_some() && self.peek() == Some('(') {
+            self.pos += 1;
+        }
+        let mut args = Vec::new();
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                None | Some('|') => return args,
+                Some(c) if Some(c) == close => {
+                    self.pos += 1;
+                    return args;
+                }
+                Some(',') => self.pos += 1,
+                Some(_) => args.push(self.value()),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Value {
+        self.skip_ws();
+        let value = match self.peek() {
+            Some('\'') | Some('"') => Value::Str(self.string()),
+            Some('[') if self.lua => match self.long_bracket() {
+                Some(s) => Value::Str(s),
+                None => {
+                    self.pos += 1;
+                    Value::Other(String::new())
+                }
+            },
+            Some('[') => self.table(']'),
+            Some('{') => self.table('}'),
+            _ => {
+                let word = self.word();
+                if word.is_empty() {
+                    self.pos += 1;
+                } else if self.lua && word == "function" {
+                    self.skip_lua_block();
+                }
+                Value::Other(word)
+            }
+        };
+        // the rest of an expression, e.g. a call such as function('name')
+        self.skip_ws();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                self.skip_past(')');
+                if self.lua {
+                    self.skip_expr();
+                }
+                Value::Other(String::new())
+            }
+            Some(c) if self.lua && !",;]})".contains(c) => {
+                self.skip_expr();
+                Value::Other(String::new())
+            }
+            _ => value,
+        }
+    }
+
+    /// Skip to the end of the current expression: the next separator or
+    /// closing bracket outside of nested brackets.
+    fn skip_expr(&mut self) {
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                None | Some(',') | Some(';') | Some(']') | Some('}') | Some(')') => return,
+                Some('(') => {
+                    self.pos += 1;
+                    self.skip_past(')');
+                }
+                Some('{') | Some('[') | Some('\'') | Some('"') => {
+                    self.value();
+                }
+                Some(_) => {
+                    if self.word().is_empty() {
+                        self.pos += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parse a Vim list (`[`) or dict (`{`), or a Lua table.
+    fn table(&mut self, close: char) -> Value {
+        let dict = close == '}' && !self.lua;
+        self.pos += 1;
+        let mut items = Vec::new();
+        let mut fields = Vec::new();
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                None => break,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(',') | Some(';') => self.pos += 1,
+                Some(_) if dict => {
+                    let key = self.value();
+                    self.skip_ws();
+                    if self.peek() == Some(':') {
+                        self.pos += 1;
+                    }
+                    let value = self.value();
+                    if let Value::Str(key) = key {
+                        fields.push((key, value));
+                    }
+                }
+                Some(_) => match self.lua_key() {
+                    Some(key) => fields.push((key, self.value())),
+                    None => items.push(self.value()),
+                },
+            }
+        }
+        Value::Table(items, fields)
+    }
+
+    /// Read the `key =` or `["key"] =` of a Lua table field, or leave the
+    /// position unchanged if the next entry is an item.
+    fn lua_key(&mut self) -> Option<String> {
+        if !self.lua {
+            return None;
+        }
+        let start = self.pos;
+        let key = if self.peek() == Some('[') && !matches!(self.peek_at(1), Some('[') | Some('=')) {
+            self.pos += 1;
+            let key = self.value().string();
+            self.skip_ws();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+            }
+            key
+        } else {
+            Some(self.word()).filter(|w| !w.is_empty() && !w.contains('.'))
+        };
+        self.skip_ws();
+        if key.is_some() && self.peek() == Some('=') && self.peek_at(1) != Some('=') {
+            self.pos += 1;
+            return key;
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Read a quoted string, unescaping it.
+    fn string(&mut self) -> String {
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                // Vim single quoted strings only escape quotes, as ''
+                '\'' if quote == '\'' && !self.lua && self.peek() == Some('\'') => {
+                    self.pos += 1;
+                    s.push('\'');
+                }
+                c if c == quote => break,
+                '\\' if self.lua || quote == '"' => {
+                    let escaped = self.peek().unwrap_or('\\');
+                    self.pos += 1;
+                    s.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        c => c,
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+        s
+    }
+
+    /// Read a Lua long string or comment, `[[...]]` or `[==[...]==]`.
+    fn long_bracket(&mut self) -> Option<String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut level = 0;
+        while self.peek() == Some('=') {
+            level += 1;
+            self.pos += 1;
+        }
+        if self.peek() != Some('[') {
+            self.pos = start;
+            return None;
+        }
+        self.pos += 1;
+        let close = format!("]{}]", "=".repeat(level));
+        let rest: String = self.chars[self.pos..].iter().collect();
+        let end = rest.find(&close).unwrap_or(rest.len());
+        let s = rest[..end].to_string();
+        self.pos += s.chars().count() + close.len();
+        self.pos = self.pos.min(self.chars.len());
+        Some(s)
+    }
+
+    /// Skip the body of a Lua function up to its `end`.
+    fn skip_lua_block(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            self.skip_ws();
+            match self.peek() {
+                None => return,
+                Some('\'') | Some('"') => {
+                    self.string();
+                }
+                Some('[') if self.long_bracket().is_some() => (),
+                Some(_) => match self.word().as_str() {
+                    "function" | "if" | "do" => depth += 1,
+                    "end" => depth -= 1,
+                    "" => self.pos += 1,
+                    _ => (),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_vim_plug() {
+        let vimrc = r#"
+call plug#begin()
+" Plug 'commented/out'
+Plug 'junegunn/vim-easy-align'
+Plug 'scrooloose/nerdtree', { 'on': ['NERDTreeToggle', 'NERDTree'] }
+Plug 'easymotion/vim-easymotion', { 'on': '<Plug>(easymotion-s)' }
+Plug 'tpope/vim-fireplace', { 'for': 'clojure' } | Plug 'tpope/vim-salve'
+Plug 'nsf/gocode', { 'tag': 'v.20150303', 'do': 'make',
+      \ 'as': 'gocode' }
+Plug 'nvim-treesitter/nvim-treesitter', {'do': ':TSUpdate'}
+Plug '~/my-prototype-plugin'
+call plug#end()
+"#;
+        let specs = parse_vim(vimrc, Format::VimPlug);
+        assert_eq!(
+            specs,
+            vec![
+                Spec::new("junegunn/vim-easy-align"),
+                Spec {
+                    on: vec!["NERDTreeToggle".to_string(), "NERDTree".to_string()],
+                    opt: true,
+                    ..Spec::new("scrooloose/nerdtree")
+                },
+                Spec {
+                    on_map: vec!["<Plug>(easymotion-s)".to_string()],
+                    opt: true,
+                    ..Spec::new("easymotion/vim-easymotion")
+                },
+                Spec {
+                    for_types: vec!["clojure".to_string()],
+                    opt: true,
+                    ..Spec::new("tpope/vim-fireplace")
+                },
+                Spec::new("tpope/vim-salve"),
+                Spec {
+                    rev: Some("v.20150303".to_string()),
+                    build: Some("make".to_string()),
+                    name: Some("gocode".to_string()),
+                    ..Spec::new("nsf/gocode")
+                },
+                Spec::new("nvim-treesitter/nvim-treesitter"),
+                Spec {
+                    local: true,
+                    ..Spec::new("~/my-prototype-plugin")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_dein() {
+        let vimrc =
+            "call dein#add('Shougo/deoplete.nvim', {'rev': 'ddc', 'on_ft': ['python', 'lua']})\n\
+                     call dein#add(\"Shougo/neosnippet.vim\", {'lazy': 1, 'build': 'make'})\n\
+                     call dein#add('Shougo/denite.nvim', {'on_event': 'InsertEnter', \
+                     'on_func': 'denite#', 'depends': 'Shougo/deoplete.nvim'})";
+        assert_eq!(
+            parse_vim(vimrc, Format::Dein),
+            vec![
+                Spec {
+                    rev: Some("ddc".to_string()),
+                    for_types: vec!["python".to_string(), "lua".to_string()],
+                    opt: true,
+                    ..Spec::new("Shougo/deoplete.nvim")
+                },
+                Spec {
+                    build: Some("make".to_string()),
+                    opt: true,
+                    ..Spec::new("Shougo/neosnippet.vim")
+                },
+                Spec {
+                    on_event: vec!["InsertEnter".to_string()],
+                    on_func: vec!["denite#".to_string()],
+                    requires: vec!["Shougo/deoplete.nvim".to_string()],
+                    opt: true,
+                    ..Spec::new("Shougo/denite.nvim")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_lazy() {
+        let lua = r#"
+-- plugins
+require("lazy").setup({
+  "folke/which-key.nvim",
+  { "folke/neoconf.nvim", cmd = "Neoconf", event = { "LazyFile", "LazyDone" }, module = "neoconf" },
+  { "folke/flash.nvim", event = "VeryLazy", keys = { { "s", mode = { "n", "x" } }, "S" } },
+  {
+    "hrsh7th/nvim-cmp",
+    branch = 'main', -- a comment
+    dependencies = {
+      "hrsh7th/cmp-buffer",
+      { "L3MON4D3/LuaSnip", build = "make install_jsregexp" },
+    },
+    config = function()
+      if true then require("cmp").setup({ sources = { "x/y" } }) end
+    end,
+  },
+  { "nvim-treesitter/nvim-treesitter", ft = { "lua", "rust" }, build = ":TSUpdate" },
+  { dir = "~/projects/secret.nvim", name = "secret" },
+  { import = "plugins.extra" },
+}, { checker = { enabled = true } })
+"#;
+        assert_eq!(
+            parse_lua(lua),
+            vec![
+                Spec::new("folke/which-key.nvim"),
+                Spec {
+                    on: vec!["Neoconf".to_string()],
+                    on_event: vec![
+                        "BufReadPost".to_string(),
+                        "BufNewFile".to_string(),
+                        "BufWritePre".to_string(),
+                    ],
+                    on_require: vec!["neoconf".to_string()],
+                    opt: true,
+                    ..Spec::new("folke/neoconf.nvim")
+                },
+                Spec {
+                    on_event: vec!["VimEnter".to_string()],
+                    on_map: vec!["s".to_string(), "S".to_string()],
+                    opt: true,
+                    ..Spec::new("folke/flash.nvim")
+                },
+                Spec {
+                    rev: Some("main".to_string()),
+                    requires: vec![
+                        "hrsh7th/cmp-buffer".to_string(),
+                        "L3MON4D3/LuaSnip".to_string(),
+                    ],
+                    ..Spec::new("hrsh7th/nvim-cmp")
+                },
+                Spec::new("hrsh7th/cmp-buffer"),
+                Spec {
+                    build: Some("make install_jsregexp".to_string()),
+                    ..Spec::new("L3MON4D3/LuaSnip")
+                },
+                Spec {
+                    for_types: vec!["lua".to_string(), "rust".to_string()],
+                    opt: true,
+                    ..Spec::new("nvim-treesitter/nvim-treesitter")
+                },
+                Spec {
+                    local: true,
+                    name: Some("secret".to_string()),
+                    ..Spec::new("~/projects/secret.nvim")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_lazy_specs() {
+        let lua = r#"
+require("lazy").setup({
+  { "nvim-telescope/telescope.nvim", dependencies = { "nvim-lua/plenary.nvim" } },
+  { "nvim-lua/plenary.nvim", cmd = "PlenaryBustedDirectory", build = "make" },
+  { url = "https://github.com/nvim-lua/plenary.nvim", ft = "lua" },
+})
+"#;
+        let specs = merge_specs(parse_lua(lua), &Settings::default());
+        assert_eq!(specs.len(), 2);
+        // the bare dependency gets the options of the full specs
+        assert_eq!(
+            specs[1],
+            Spec {
+                on: vec!["PlenaryBustedDirectory".to_string()],
+                for_types: vec!["lua".to_string()],
+                build: Some("make".to_string()),
+                opt: true,
+                ..Spec::new("nvim-lua/plenary.nvim")
+            }
+        );
+    }
+}
@@ -1,21 +1,34 @@
-use crate::git::GitRepo;
+use crate::git::{GitRepo, HeadChange, LocalChanges};
 use crate::package::{self, Package};
+use crate::report::{PackReporter, Progress};
 use crate::task::{TaskManager, TaskType};
 use crate::{Error, Result};
 
 use clap::{value_t, ArgMatches};
+use std::fs;
 
 #[derive(Debug)]
 struct InstallArgs {
     plugins: Vec<String>,
     on: Option<String>,
     for_: Option<String>,
+    on_event: Option<String>,
+    on_map: Option<String>,
+    on_func: Option<String>,
+    on_path: Option<String>,
+    on_require: Option<String>,
+    requires: Option<String>,
     as_: Option<String>,
     threads: Option<usize>,
+    progress: Progress,
     opt: bool,
     category: String,
     build: Option<String>,
     rev: Option<String>,
+    depth: Option<u32>,
+    locked: bool,
+    local: bool,
+    json: bool,
 }
 
 impl InstallArgs {
@@ -24,12 +37,24 @@ impl InstallArgs {
             plugins: m.values_of_lossy("package").unwrap_or_default(),
             on: value_t!(m, "on", String).ok(),
             for_: value_t!(m, "for", String).ok(),
+            on_event: value_t!(m, "on-event", String).ok(),
+            on_map: value_t!(m, "on-map", String).ok(),
+            on_func: value_t!(m, "on-func", String).ok(),
+            on_path: value_t!(m, "on-path", String).ok(),
+            on_require: value_t!(m, "on-require", String).ok(),
+            requires: value_t!(m, "requires", String).ok(),
             as_: value_t!(m, "as", String).ok(),
             threads: value_t!(m, "threads", usize).ok(),
+            progress: Progress::from_matches(m),
             opt: m.is_present("opt"),
-            category: value_t!(m, "category", String).unwrap_or_default(),
+            category: value_t!(m, "category", String)
+                .unwrap_or_else(|_| package::settings().category.clone()),
             build: value_t!(m, "build", String).ok(),
             rev: value_t!(m, "rev", String).ok(),
+            depth: value_t!(m, "depth", u32).ok(),
+            locked: m.is_present("locked"),
+            local: m.is_present("local"),
+            json: m.is_present("json"),
         }
     }
 }
@@ -39,72 +64,128 @@ pub fn exec(matches: &ArgMatches) {
 
     // This check cannot be done with clap
     if args.as_.is_some() && args.plugins.len() > 1 {
-        die!("Multiple plugins cannot be specified with --as");
+        die_json!(args.json, "Multiple plugins cannot be specified with --as");
     }
 
+    let settings = package::settings();
     let threads = match args.threads {
         Some(t) => t,
-        _ => num_cpus::get(),
+        _ => settings.threads(),
     };
 
     if threads < 1 {
-        die!("Threads should be greater than 0");
+        die_json!(args.json, "Threads should be greater than 0");
     }
 
-    let opt = args.on.is_some() || args.for_.is_some() || args.opt;
-    let types = args
-        .for_
-        .clone() // map consumes value but we need it in next block
-        .map(|e| e.split(',').map(|e| e.to_string()).collect::<Vec<String>>())
-        .unwrap_or_default();
+    if args.depth == Some(0) {
+        die_json!(args.json, "Depth should be greater than 0");
+    }
+
+    let triggers = [
+        &args.for_,
+        &args.on_event,
+        &args.on_map,
+        &args.on_func,
+        &args.on_path,
+        &args.on_require,
+    ];
+    let opt = args.on.is_some() || triggers.iter().any(|t| t.is_some()) || args.opt;
+    // comma separated lists
+    let split = |list: &Option<String>| {
+        list.as_ref()
+            .map(|e| e.split(',').map(|e| e.to_string()).collect::<Vec<String>>())
+            .unwrap_or_default()
+    };
+    let types = split(&args.for_);
 
     let plugins = args
         .plugins
         .iter()
         .map(|plug| {
-            // URL to git clone from
-            let remote = if !plug.starts_with("https://") {
-                format!("https://github.com/{}", plug)
+            // URL to git clone from, or absolute path of a local plugin
+            let remote = if args.local {
+                match fs::canonicalize(plug) {
+                    Ok(path) => path.to_string_lossy().into_owned(),
+                    Err(e) => die_json!(args.json, "Fail to find local plugin {}: {}", plug, e),
+                }
             } else {
-                plug.clone()
+                settings.expand_remote(plug)
             };
 
             // Install package under this name. Defaults to repo name
             let name = args
                 .as_
-                // unwrap_or_else consumes self so deref to Option<&str>
-                .as_deref()
-                .unwrap_or_else(|| remote.rsplitn(2, '/').next().unwrap());
+                .clone()
+                .unwrap_or_else(|| Package::name_from_remote(&remote));
 
             // FIXME: too many clones
             Package {
-                name: name.to_string(),
+                name,
                 idname: Package::idname_from_remote(&remote),
                 remote,
+                local: args.local,
                 revision: args.rev.clone(),
                 category: args.category.clone(),
                 opt,
                 for_types: types.clone(),
-                load_command: args.on.clone(),
+                on_event: split(&args.on_event),
+                on_map: split(&args.on_map),
+                on_func: split(&args.on_func),
+                on_path: split(&args.on_path),
+                on_require: split(&args.on_require),
+                load_commands: split(&args.on),
+                requires: split(&args.requires),
+                auto: false,
                 build_command: args.build.clone(),
+                depth: args.depth,
             }
         })
         .collect::<Vec<_>>();
 
-    if let Err(e) = install_plugins(plugins, threads) {
-        die!("Err: {}", e);
+    if let Err(e) = install_plugins(
+        plugins,
+        threads,
+        args.progress,
+        args.depth,
+        args.locked,
+        args.json,
+    ) {
+        die_json!(args.json, "Err: {}", e);
     }
 }
 
+/// Install packages described elsewhere, e.g. imported from another plugin
+/// manager, and add them to paconfig.
+pub fn install_packages(
+    packs: Vec<Package>,
+    threads: usize,
+    progress: Progress,
+    json: bool,
+) -> Result<()> {
+    install_plugins(packs, threads, progress, None, false, json)
+}
+
 // FIXME: refactor this
-fn install_plugins(toinstall_packs: Vec<Package>, threads: usize) -> Result<()> {
+/// `depth` applies to plugins not cloned yet, `locked` checks out the
+/// commits of `paconfig.lock`.
+fn install_plugins(
+    toinstall_packs: Vec<Package>,
+    threads: usize,
+    progress: Progress,
+    depth: Option<u32>,
+    locked: bool,
+    json: bool,
+) -> Result<()> {
     let mut installed_packs = package::fetch()?;
     {
-        let mut manager = TaskManager::new(TaskType::Install, threads);
-
+        let mut queue = Vec::new();
         if toinstall_packs.is_empty() {
-            for pack in &installed_packs {
-                manager.add(pack.clone());
+            for pack in installed_packs.iter_mut() {
+                // --depth only applies to plugins not cloned yet
+                if pack.depth.is_none() && !pack.is_installed() {
+                    pack.depth = depth;
+                }
+                queue.push(pack.clone());
             }
         } else {
             for mut toins_pack in toinstall_packs {
@@ -113,14 +194,23 @@ fn install_plugins(toinstall_packs: Vec<Package>, threads: usize) -> Result<()>
                     .find(|ins_pack| ins_pack.idname == toins_pack.idname)
                 {
                     Some(ins_pack) => {
+                        // installed explicitly, no longer only a requirement
+                        ins_pack.auto = false;
                         // plugin in config file but not installed
                         if !ins_pack.is_installed() {
                             ins_pack.set_category(toins_pack.category.as_str());
                             ins_pack.set_opt(toins_pack.opt);
                             ins_pack.set_types(toins_pack.for_types.clone());
+                            ins_pack.on_event = toins_pack.on_event.clone();
+                            ins_pack.on_map = toins_pack.on_map.clone();
+                            ins_pack.on_func = toins_pack.on_func.clone();
+                            ins_pack.on_path = toins_pack.on_path.clone();
+                            ins_pack.on_require = toins_pack.on_require.clone();
+                            ins_pack.requires = toins_pack.requires.clone();
 
-                            ins_pack.load_command = toins_pack.load_command.clone();
+                            ins_pack.load_commands = toins_pack.load_commands.clone();
                             ins_pack.build_command = toins_pack.build_command.clone();
+                            ins_pack.depth = toins_pack.depth;
                         } else {
                             toins_pack.set_category(ins_pack.category.as_str());
                             toins_pack.set_opt(ins_pack.opt);
@@ -133,23 +223,98 @@ fn install_plugins(toinstall_packs: Vec<Package>, threads: usize) -> Result<()>
                     // not yet installed, but add it anyway
                     installed_packs.push(toins_pack.clone());
                 }
-                manager.add(toins_pack.clone());
+                queue.push(toins_pack);
             }
         }
 
-        for fail in manager.run(install_plugin) {
+        // pull in the requirements missing from paconfig or not installed yet,
+        // under the category of the plugin requiring them, marked as auto so
+        // that `uninstall --with-orphans` can remove them
+        let mut i = 0;
+        while i < queue.len() {
+            let pack = installed_packs
+                .iter()
+                .find(|p| p.idname == queue[i].idname)
+                .unwrap_or(&queue[i])
+                .clone();
+            for (req, idname) in pack.requires.iter().zip(pack.required_idnames()) {
+                if queue.iter().any(|p| p.idname == idname) {
+                    continue;
+                }
+                match installed_packs.iter().find(|p| p.idname == idname) {
+                    Some(p) if p.is_installed() => (),
+                    Some(p) => queue.push(p.clone()),
+                    None => {
+                        let remote = package::settings().expand_remote(req);
+                        let name = Package::name_from_remote(&remote);
+                        let mut dep = Package::new(&name, &remote, &pack.category, pack.opt);
+                        dep.auto = true;
+                        installed_packs.push(dep.clone());
+                        queue.push(dep);
+                    }
+                }
+            }
+            i += 1;
+        }
+        package::check_requires(&installed_packs)?;
+
+        // local plugins are only symlinked, no need to sync them
+        let (local_packs, queue): (Vec<_>, Vec<_>) = queue.into_iter().partition(|p| p.local);
+        for pack in &local_packs {
+            match pack.link_local() {
+                Ok(true) if !json => println!("Linked {} to {}", pack.idname, pack.remote),
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("Fail to link {}: {}", pack.idname, e);
+                    installed_packs.retain(|p| p.idname != pack.idname);
+                }
+            }
+        }
+        if queue.is_empty() && !local_packs.is_empty() {
+            return save_plugins(installed_packs);
+        }
+
+        let mut manager = TaskManager::new(TaskType::Install, threads);
+        manager.set_progress(progress);
+        manager.set_json(json);
+        for pack in queue {
+            manager.add(pack);
+        }
+
+        let func = if locked {
+            let lock = package::fetch_lock()?;
+            manager.map(|pack| {
+                if let Some(commit) = lock.get(&pack.idname) {
+                    pack.revision = Some(commit.clone());
+                }
+            });
+            install_locked_plugin
+        } else {
+            install_plugin
+        };
+
+        let outcome = manager.run(func);
+        if json {
+            println!("{}", outcome.to_json());
+        }
+        for fail in outcome.failures {
             installed_packs.retain(|e| e.idname != fail);
         }
     }
 
-    installed_packs.sort_by(|a, b| a.idname.cmp(&b.idname));
+    save_plugins(installed_packs)
+}
+
+fn save_plugins(mut packs: Vec<Package>) -> Result<()> {
+    packs.sort_by(|a, b| a.idname.cmp(&b.idname));
 
-    package::update_pac_plugin(&installed_packs)?;
-    package::save(installed_packs)
+    package::update_pac_plugin(&packs)?;
+    package::save_lock(&packs)?;
+    package::save(packs)
 }
 
-fn install_plugin(pack: &Package) -> (Result<()>, bool) {
-    let res = do_install(pack);
+fn install_plugin(pack: &Package, progress: &PackReporter) -> (Result<HeadChange>, bool) {
+    let res = do_install(pack, progress);
     let status = match res {
         Err(Error::PluginInstalled(_)) => true,
         Err(_) => false,
@@ -158,11 +323,28 @@ fn install_plugin(pack: &Package) -> (Result<()>, bool) {
     (res, status)
 }
 
-fn do_install(pack: &Package) -> Result<()> {
+/// Like `install_plugin`, but also checks out already installed plugins
+/// (the revision is expected to be the locked commit).
+fn install_locked_plugin(pack: &Package, progress: &PackReporter) -> (Result<HeadChange>, bool) {
+    let res = if pack.is_installed() {
+        // install has no --force or --stash to suggest
+        match pack.git_status() {
+            Ok(s) if !s.modified.is_empty() => Err(Error::locked_local_changes(s.modified.len())),
+            _ => pack.git_pull(LocalChanges::Refuse, progress),
+        }
+    } else {
+        pack.git_clone(progress)
+    };
+    // failures are dropped from paconfig, keep the plugins already installed
+    let status = res.is_ok() || pack.is_installed();
+    (res, status)
+}
+
+fn do_install(pack: &Package, progress: &PackReporter) -> Result<HeadChange> {
     let path = pack.path();
     if path.is_dir() {
         Err(Error::plugin_installed(&path))
     } else {
-        pack.git_clone()
+        pack.git_clone(progress)
     }
 }
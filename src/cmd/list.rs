@@ -1,3 +1,4 @@
+use crate::json::Json;
 use crate::package::{self, Package};
 use crate::Result;
 
@@ -9,6 +10,7 @@ struct ListArgs {
     opt: bool,
     detached: bool,
     category: Option<String>,
+    json: bool,
 }
 
 impl ListArgs {
@@ -18,15 +20,17 @@ impl ListArgs {
             opt: m.is_present("opt"),
             detached: m.is_present("detached"),
             category: value_t!(m, "category", String).ok(),
+            json: m.is_present("json"),
         }
     }
 }
 
 pub fn exec(matches: &ArgMatches) {
     let args = ListArgs::from_matches(matches);
+    let json = args.json;
 
     if let Err(e) = list_packages(args) {
-        die!("Err: {}", e);
+        die_json!(json, "Err: {}", e);
     }
 }
 
@@ -36,10 +40,10 @@ fn list_packages(args: ListArgs) -> Result<()> {
     } else {
         list_installed
     };
-    f(&args.category, args.start, args.opt)
+    f(&args.category, args.start, args.opt, args.json)
 }
 
-fn list_installed(category: &Option<String>, start: bool, opt: bool) -> Result<()> {
+fn list_installed(category: &Option<String>, start: bool, opt: bool, json: bool) -> Result<()> {
     let packs = package::fetch()?;
 
     let filter = |x: &Package| -> bool {
@@ -56,19 +60,44 @@ fn list_installed(category: &Option<String>, start: bool, opt: bool) -> Result<(
         status
     };
 
+    if json {
+        let records = packs
+            .iter()
+            .filter(|p| filter(p))
+            .map(Package::to_json)
+            .collect();
+        println!("{}", Json::Array(records));
+        return Ok(());
+    }
+
     for p in packs.into_iter().filter(filter) {
         println!("{}", p);
     }
     Ok(())
 }
 
-fn list_detached(category: &Option<String>, start: bool, opt: bool) -> Result<()> {
+fn list_detached(category: &Option<String>, start: bool, opt: bool, json: bool) -> Result<()> {
     let installed = package::fetch()?;
     let pack_names: Vec<&str> = installed.iter().map(|p| &*p.name).collect();
 
+    let mut records = Vec::new();
     package::walk_packs(category, start, opt, |cate, option, name| {
-        if !pack_names.contains(&name) {
+        if pack_names.contains(&name) {
+            return;
+        }
+        if json {
+            records.push(Json::object(vec![
+                ("category", Json::from(cate)),
+                ("opt", Json::from(option == "opt")),
+                ("name", Json::from(name)),
+            ]));
+        } else {
             println!("{}/{}/{}", cate, option, name);
         }
-    })
+    })?;
+
+    if json {
+        println!("{}", Json::Array(records));
+    }
+    Ok(())
 }
@@ -1,6 +1,13 @@
+pub mod adopt;
+pub mod clean;
+pub mod export;
 pub mod generate;
+pub mod import;
 pub mod install;
 pub mod list;
 pub mod move_cmd;
+pub mod outdated;
+pub mod restore;
+pub mod status;
 pub mod uninstall;
 pub mod update;
@@ -32,6 +32,8 @@ pub fn exec(matches: &ArgMatches) {
 
 fn move_plugin(plugin: &str, category: &str, opt: bool) -> Result<()> {
     let mut packs = package::fetch()?;
+    let idname = package::settings().resolve_idname(&packs, plugin);
+    let plugin = idname.as_str();
     let changed = {
         let pack = match packs.iter_mut().find(|p| p.idname == plugin) {
             Some(p) => p,
@@ -47,10 +49,17 @@ fn move_plugin(plugin: &str, category: &str, opt: bool) -> Result<()> {
             package::Package::new(plugin, &pack.remote, category, opt)
                 .path();
         if origin_path != path {
-            utils::copy_directory(&origin_path, &path)?;
-            fs::remove_dir_all(&origin_path)?;
+            if pack.local {
+                fs::remove_file(&origin_path)?;
+            } else {
+                utils::copy_directory(&origin_path, &path)?;
+                fs::remove_dir_all(&origin_path)?;
+            }
             pack.set_category(category as &str);
             pack.set_opt(opt);
+            if pack.local {
+                pack.link_local()?;
+            }
             true
         } else {
             false
@@ -0,0 +1,92 @@
+use crate::git::{GitRepo, HeadChange};
+use crate::package::{self, Package};
+use crate::report::{PackReporter, Progress};
+use crate::task::{TaskManager, TaskType};
+use crate::{Error, Result};
+
+use clap::{value_t, ArgMatches};
+
+#[derive(Debug)]
+struct OutdatedArgs {
+    plugins: Vec<String>,
+    threads: Option<usize>,
+    progress: Progress,
+}
+
+impl OutdatedArgs {
+    fn from_matches(m: &ArgMatches) -> OutdatedArgs {
+        OutdatedArgs {
+            plugins: m.values_of_lossy("package").unwrap_or_default(),
+            threads: value_t!(m, "threads", usize).ok(),
+            progress: Progress::from_matches(m),
+        }
+    }
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = OutdatedArgs::from_matches(matches);
+
+    let threads = args
+        .threads
+        .unwrap_or_else(|| package::settings().threads());
+    if threads < 1 {
+        die!("Threads should be greater than 0");
+    }
+
+    if let Err(e) = check_plugins(&args.plugins, threads, args.progress) {
+        die!("Err: {}", e);
+    }
+}
+
+/// Fetch the tracked revision of every plugin and print the ones that are
+/// behind it, without touching the working tree.
+fn check_plugins(plugins: &[String], threads: usize, progress: Progress) -> Result<()> {
+    let packs = package::fetch()?;
+    let plugins = plugins
+        .iter()
+        .map(|p| package::settings().resolve_idname(&packs, p))
+        .collect::<Vec<_>>();
+
+    let mut manager = TaskManager::new(TaskType::Check, threads);
+    manager.set_progress(progress);
+    for pack in packs
+        .iter()
+        .filter(|x| plugins.is_empty() || plugins.contains(&x.idname))
+    {
+        manager.add(pack.clone());
+    }
+
+    let mut outcome = manager.run(check_plugin);
+    outcome.changes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut outdated = 0;
+    for (idname, change) in outcome.changes.iter().filter(|(_, c)| c.is_changed()) {
+        let pack = match packs.iter().find(|p| &p.idname == idname) {
+            Some(p) => p,
+            None => continue,
+        };
+        match pack.commits_behind(change) {
+            Ok(Some(n)) => println!("{} is {} commit(s) behind", idname, n),
+            Ok(None) => println!("{} is behind (shallow clone, commits not counted)", idname),
+            Err(e) => println!("Fail to count new commits of {}: {}", idname, e),
+        }
+        outdated += 1;
+    }
+    // local and pinned packages are skipped, not checked
+    if outdated == 0 && !outcome.changes.is_empty() {
+        println!("All checked packages are up to date");
+    }
+    Ok(())
+}
+
+fn check_plugin(pack: &Package, progress: &PackReporter) -> (Result<HeadChange>, bool) {
+    let res = if pack.local {
+        Err(Error::SkipLocal)
+    } else if pack.is_installed() {
+        pack.git_fetch_head(progress)
+    } else {
+        Err(Error::plugin_not_installed(&pack.idname))
+    };
+    let status = res.is_ok();
+    (res, status)
+}
@@ -0,0 +1,109 @@
+use crate::git::{GitRepo, HeadChange, LocalChanges};
+use crate::package::{self, Package};
+use crate::report::{PackReporter, Progress};
+use crate::task::{TaskManager, TaskType};
+use crate::{Error, Result};
+
+use clap::{value_t, ArgMatches};
+
+#[derive(Debug)]
+struct RestoreArgs {
+    snapshot: Option<String>,
+    threads: Option<usize>,
+    progress: Progress,
+    list: bool,
+    local_changes: LocalChanges,
+}
+
+impl RestoreArgs {
+    fn from_matches(m: &ArgMatches) -> RestoreArgs {
+        RestoreArgs {
+            snapshot: value_t!(m, "snapshot", String).ok(),
+            threads: value_t!(m, "threads", usize).ok(),
+            progress: Progress::from_matches(m),
+            list: m.is_present("list"),
+            local_changes: if m.is_present("force") {
+                LocalChanges::Discard
+            } else if m.is_present("stash") {
+                LocalChanges::Stash
+            } else {
+                LocalChanges::Refuse
+            },
+        }
+    }
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = RestoreArgs::from_matches(matches);
+
+    if args.list {
+        if let Err(e) = list_snapshots() {
+            die!("Err: {}", e);
+        }
+        return;
+    }
+
+    let threads = args
+        .threads
+        .unwrap_or_else(|| package::settings().threads());
+    if threads < 1 {
+        die!("Threads should be greater than 0");
+    }
+
+    if let Err(e) = restore_snapshot(&args, threads) {
+        die!("Err: {}", e);
+    }
+}
+
+fn list_snapshots() -> Result<()> {
+    for name in package::list_snapshots()? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Checkout every plugin recorded in the snapshot to the commit it
+/// was on before the update.
+fn restore_snapshot(args: &RestoreArgs, threads: usize) -> Result<()> {
+    let entries = package::fetch_snapshot(args.snapshot.as_deref())?;
+    let packs = package::fetch()?;
+
+    let torestore = entries
+        .iter()
+        .filter(|e| e.before != e.after)
+        .filter_map(|e| {
+            let mut pack = packs.iter().find(|p| p.idname == e.idname)?.clone();
+            pack.revision = Some(e.before.clone());
+            Some(pack)
+        })
+        .collect::<Vec<_>>();
+    if torestore.is_empty() {
+        println!("Nothing to restore");
+        return Ok(());
+    }
+
+    let mut manager = TaskManager::new(TaskType::Update, threads);
+    manager.set_progress(args.progress);
+    for pack in torestore {
+        manager.add(pack);
+    }
+    let local_changes = args.local_changes;
+    let outcome = manager.run(move |pack, progress| restore_plugin(pack, local_changes, progress));
+    package::save_lock(&packs)?;
+
+    if !outcome.failures.is_empty() {
+        return Err(Error::restore_failed(&outcome.failures));
+    }
+    println!("Restored {} plugin(s)", outcome.changes.len());
+    Ok(())
+}
+
+fn restore_plugin(
+    pack: &Package,
+    local_changes: LocalChanges,
+    progress: &PackReporter,
+) -> (Result<HeadChange>, bool) {
+    let res = pack.git_checkout(local_changes, progress);
+    let status = res.is_ok();
+    (res, status)
+}
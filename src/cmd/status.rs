@@ -0,0 +1,136 @@
+use crate::git::{GitRepo, RepoStatus};
+use crate::json::Json;
+use crate::package::{self, Package};
+use crate::Result;
+
+use clap::ArgMatches;
+
+#[derive(Debug)]
+struct StatusArgs {
+    plugins: Vec<String>,
+    verbose: bool,
+    json: bool,
+}
+
+impl StatusArgs {
+    fn from_matches(m: &ArgMatches) -> StatusArgs {
+        StatusArgs {
+            plugins: m.values_of_lossy("package").unwrap_or_default(),
+            verbose: m.is_present("verbose"),
+            json: m.is_present("json"),
+        }
+    }
+}
+
+pub fn exec(matches: &ArgMatches) {
+    let args = StatusArgs::from_matches(matches);
+
+    if let Err(e) = show_status(&args.plugins, args.verbose, args.json) {
+        die_json!(args.json, "Err: {}", e);
+    }
+}
+
+fn show_status(plugins: &[String], verbose: bool, json: bool) -> Result<()> {
+    let packs = package::fetch()?;
+    let plugins = plugins
+        .iter()
+        .map(|p| package::settings().resolve_idname(&packs, p))
+        .collect::<Vec<_>>();
+    let packs = packs
+        .iter()
+        .filter(|x| plugins.is_empty() || plugins.contains(&x.idname));
+
+    if json {
+        println!("{}", Json::Array(packs.map(to_json).collect()));
+        return Ok(());
+    }
+
+    for pack in packs {
+        if !pack.is_installed() {
+            println!("{} => not installed", pack.idname);
+            continue;
+        }
+        if pack.local {
+            println!("{} => local, linked to {}", pack.idname, pack.remote);
+            continue;
+        }
+        match pack.git_status() {
+            Ok(status) => {
+                println!("{} => {}", pack.idname, describe(pack, &status).join(", "));
+                if verbose {
+                    for path in &status.modified {
+                        println!("    {}", path);
+                    }
+                }
+            }
+            Err(e) => println!("{} => {}", pack.idname, e),
+        }
+    }
+    Ok(())
+}
+
+/// One record per package. Fields describing the repo are null if it is not
+/// installed, local or cannot be read, in which case `error` says why.
+fn to_json(pack: &Package) -> Json {
+    let (status, error) = if !pack.is_installed() {
+        (None, Some("not installed".to_string()))
+    } else if pack.local {
+        (None, None)
+    } else {
+        match pack.git_status() {
+            Ok(s) => (Some(s), None),
+            Err(e) => (None, Some(e.to_string())),
+        }
+    };
+    let status = status.as_ref();
+    Json::object(vec![
+        ("idname", Json::from(pack.idname.as_str())),
+        ("local", Json::from(pack.local)),
+        ("error", Json::from(error)),
+        ("modified", Json::from(status.map(|s| s.modified.clone()))),
+        ("branch", Json::from(status.and_then(|s| s.branch.clone()))),
+        ("head", Json::from(status.map(|s| s.head.to_string()))),
+        ("rev", Json::from(pack.revision.clone())),
+        (
+            "rev_commit",
+            Json::from(status.and_then(|s| s.revision).map(|o| o.to_string())),
+        ),
+        (
+            "remote",
+            Json::from(status.and_then(|s| s.remote_url.clone())),
+        ),
+    ])
+}
+
+/// Returns the notable parts of a repo's state, comparing it with the
+/// package configuration.
+fn describe(pack: &Package, status: &RepoStatus) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    if status.modified.is_empty() {
+        notes.push("clean".to_string());
+    } else {
+        notes.push(format!("{} modified file(s)", status.modified.len()));
+    }
+
+    let head = status.head.to_string();
+    match status.branch {
+        Some(ref b) => notes.push(format!("on {}", b)),
+        None => notes.push(format!("detached at {}", &head[..7])),
+    }
+
+    if let Some(ref rev) = pack.revision {
+        match status.revision {
+            Some(oid) if oid == status.head => (),
+            Some(_) => notes.push(format!("HEAD does not match rev {}", rev)),
+            None => notes.push(format!("rev {} not found", rev)),
+        }
+    }
+
+    if let Some(ref url) = status.remote_url {
+        if url != &pack.remote {
+            notes.push(format!("remote is {}", url));
+        }
+    }
+    notes
+}
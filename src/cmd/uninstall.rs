@@ -7,12 +7,16 @@ use std::fs;
 #[derive(Debug)]
 struct UninstallArgs {
     plugins: Vec<String>,
+    force: bool,
+    with_orphans: bool,
 }
 
 impl UninstallArgs {
     fn from_matches(m: &ArgMatches) -> UninstallArgs {
         UninstallArgs {
             plugins: m.values_of_lossy("package").unwrap_or_default(),
+            force: m.is_present("force"),
+            with_orphans: m.is_present("with-orphans"),
         }
     }
 }
@@ -20,15 +24,37 @@ impl UninstallArgs {
 pub fn exec(matches: &ArgMatches) {
     let args = UninstallArgs::from_matches(matches);
 
-    if let Err(e) = uninstall_plugins(&args.plugins) {
+    if let Err(e) = uninstall_plugins(&args.plugins, args.force, args.with_orphans) {
         die!("{}", e);
     }
 }
 
-/// Uninstall multiple plugins based on plugin names.
-fn uninstall_plugins(plugins: &[String]) -> Result<()> {
+/// Uninstall multiple plugins based on plugin names. Plugins required by
+/// the remaining ones are kept unless `force`.
+fn uninstall_plugins(plugins: &[String], force: bool, with_orphans: bool) -> Result<()> {
     let mut packs = package::fetch()?;
 
+    let mut plugins = plugins
+        .iter()
+        .map(|p| package::settings().resolve_idname(&packs, p))
+        .collect::<Vec<_>>();
+    if with_orphans {
+        plugins.extend(orphans(&plugins, &packs));
+    }
+    if !force {
+        for plugin in &plugins {
+            let by = packs
+                .iter()
+                .filter(|p| !plugins.contains(&p.idname))
+                .filter(|p| p.required_idnames().contains(plugin))
+                .map(|p| p.idname.as_str())
+                .collect::<Vec<_>>();
+            if !by.is_empty() {
+                return Err(Error::required_by(plugin, &by));
+            }
+        }
+    }
+
     let to_uninstall = plugins
         .iter()
         .map(
@@ -46,6 +72,7 @@ fn uninstall_plugins(plugins: &[String]) -> Result<()> {
     packs.retain(|x| !plugins.contains(&x.idname)); // keep only installed plugins
     packs.sort_by(|a, b| a.idname.cmp(&b.idname));
     package::update_pac_plugin(&packs)?;
+    package::save_lock(&packs)?;
     package::save(packs)?;
 
     println!();
@@ -53,13 +80,73 @@ fn uninstall_plugins(plugins: &[String]) -> Result<()> {
     Ok(())
 }
 
+/// Packages installed as requirements (auto) of `removed`, directly or not,
+/// that no other package requires.
+fn orphans(removed: &[String], packs: &[Package]) -> Vec<String> {
+    let mut removed = removed.to_vec();
+    let mut orphans = Vec::new();
+    loop {
+        let found = packs
+            .iter()
+            .filter(|p| p.auto && !removed.contains(&p.idname))
+            .filter(|p| {
+                let requires = |q: &&Package| q.required_idnames().contains(&p.idname);
+                let (by_removed, by_kept): (Vec<_>, Vec<_>) = packs
+                    .iter()
+                    .filter(requires)
+                    .partition(|q| removed.contains(&q.idname));
+                !by_removed.is_empty() && by_kept.is_empty()
+            })
+            .map(|p| p.idname.clone())
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            return orphans;
+        }
+        removed.extend(found.iter().cloned());
+        orphans.extend(found);
+    }
+}
+
 /// Uninstall a specific plugin.
 fn uninstall_plugin(plugin: &Package) -> Result<()> {
     let plugin_path = plugin.path();
 
-    if plugin_path.is_dir() {
+    // only remove the symlink of local plugins, even if it is broken
+    if plugin.local {
+        if fs::symlink_metadata(&plugin_path).is_ok() {
+            fs::remove_file(&plugin_path)?;
+        }
+    } else if plugin_path.is_dir() {
         fs::remove_dir_all(&plugin_path)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::pack;
+
+    #[test]
+    fn orphans_of_removed() {
+        let auto = |mut p: Package| {
+            p.auto = true;
+            p
+        };
+        let packs = vec![
+            pack("cmp-buffer", &["u/nvim-cmp", "u/cmp-lsp"]),
+            auto(pack("nvim-cmp", &["u/plenary"])),
+            pack("cmp-lsp", &[]),
+            auto(pack("plenary", &[])),
+            pack("telescope", &["u/plenary"]),
+            pack("unrelated", &[]),
+        ];
+        // cmp-lsp was installed explicitly, it stays
+        let removed = vec!["u/cmp-buffer".to_string()];
+        assert_eq!(orphans(&removed, &packs), vec!["u/nvim-cmp"]);
+        // plenary is no longer needed once telescope goes too
+        let removed = vec!["u/cmp-buffer".to_string(), "u/telescope".to_string()];
+        assert_eq!(orphans(&removed, &packs), vec!["u/nvim-cmp", "u/plenary"]);
+    }
+}
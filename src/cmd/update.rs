@@ -1,5 +1,6 @@
-use crate::git::GitRepo;
+use crate::git::{GitRepo, HeadChange, LocalChanges};
 use crate::package::{self, Package};
+use crate::report::{PackReporter, Progress};
 use crate::task::{TaskManager, TaskType};
 use crate::{Error, Result};
 use clap::{value_t, ArgMatches};
@@ -9,6 +10,10 @@ struct UpdateArgs {
     plugins: Vec<String>,
     skip: Vec<String>,
     threads: Option<usize>,
+    progress: Progress,
+    log_format: Option<String>,
+    local_changes: LocalChanges,
+    json: bool,
     paconfig: bool,
 }
 
@@ -18,6 +23,20 @@ impl UpdateArgs {
             plugins: m.values_of_lossy("package").unwrap_or_default(),
             skip: m.values_of_lossy("skip").unwrap_or_default(),
             threads: value_t!(m, "threads", usize).ok(),
+            progress: Progress::from_matches(m),
+            log_format: if m.is_present("log") || m.occurrences_of("log-format") > 0 {
+                value_t!(m, "log-format", String).ok()
+            } else {
+                None
+            },
+            local_changes: if m.is_present("force") {
+                LocalChanges::Discard
+            } else if m.is_present("stash") {
+                LocalChanges::Stash
+            } else {
+                LocalChanges::Refuse
+            },
+            json: m.is_present("json"),
             // TODO: remove this opt (already removed from cli)
             paconfig: m.is_present("paconfig"),
         }
@@ -29,18 +48,20 @@ pub fn exec(matches: &ArgMatches) {
 
     if args.paconfig {
         if let Err(e) = update_paconfig() {
-            die!("Err: {}", e);
+            die_json!(args.json, "Err: {}", e);
         }
         return;
     }
 
-    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    let threads = args
+        .threads
+        .unwrap_or_else(|| package::settings().threads());
     if threads < 1 {
-        die!("Threads should be greater than 0");
+        die_json!(args.json, "Threads should be greater than 0");
     }
 
-    if let Err(e) = update_plugins(&args.plugins, threads, &args.skip) {
-        die!("Err: {}", e);
+    if let Err(e) = update_plugins(&args, threads) {
+        die_json!(args.json, "Err: {}", e);
     }
 }
 
@@ -54,14 +75,24 @@ fn update_paconfig() -> Result<()> {
     Ok(())
 }
 
-fn update_plugins(plugins: &[String], threads: usize, skip: &[String]) -> Result<()> {
+fn update_plugins(args: &UpdateArgs, threads: usize) -> Result<()> {
     let mut packs = package::fetch()?;
+    let plugins = args
+        .plugins
+        .iter()
+        .map(|p| package::settings().resolve_idname(&packs, p))
+        .collect::<Vec<_>>();
+    let skip = &args.skip;
 
     let mut manager = TaskManager::new(TaskType::Update, threads);
+    manager.set_progress(args.progress);
+    manager.set_json(args.json);
     if plugins.is_empty() {
         for pack in &packs {
             if skip.iter().any(|x| pack.idname.contains(x)) {
-                println!("Skip {}", pack.idname);
+                if !args.json {
+                    println!("Skip {}", pack.idname);
+                }
                 continue;
             }
             manager.add(pack.clone());
@@ -72,7 +103,37 @@ fn update_plugins(plugins: &[String], threads: usize, skip: &[String]) -> Result
         }
     }
 
-    for fail in manager.run(update_plugin) {
+    let local_changes = args.local_changes;
+    let mut outcome =
+        manager.run(move |pack, progress| update_plugin(pack, local_changes, progress));
+    outcome.changes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if args.json {
+        println!("{}", outcome.to_json());
+    } else if let Some(ref format) = args.log_format {
+        print_log(&packs, &outcome.changes, format);
+    }
+
+    let snapshot = outcome
+        .changes
+        .iter()
+        .filter_map(|(idname, change)| {
+            Some(package::SnapshotEntry {
+                idname: idname.clone(),
+                before: change.old?.to_string(),
+                after: change.new.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    if outcome.changes.iter().any(|(_, c)| c.is_changed()) {
+        let name = package::save_snapshot(&snapshot)?;
+        if !args.json {
+            println!("Saved snapshot {} (use `pac restore` to roll back)", name);
+        }
+    }
+    package::save_lock(&packs)?;
+
+    for fail in outcome.failures {
         packs.retain(|e| e.idname != fail);
     }
 
@@ -83,21 +144,50 @@ fn update_plugins(plugins: &[String], threads: usize, skip: &[String]) -> Result
     Ok(())
 }
 
-fn update_plugin(pack: &Package) -> (Result<()>, bool) {
-    let res = do_update(pack);
+/// Print the new commits of every package that changed during the update.
+fn print_log(packs: &[Package], changes: &[(String, HeadChange)], format: &str) {
+    for (idname, change) in changes.iter().filter(|(_, c)| c.is_changed()) {
+        let pack = match packs.iter().find(|p| &p.idname == idname) {
+            Some(p) => p,
+            None => continue,
+        };
+        println!("{}", idname);
+        match pack.git_log(change, format) {
+            Ok(log) => {
+                for line in log {
+                    println!("    {}", line);
+                }
+            }
+            Err(e) => println!("    Fail to read log: {}", e),
+        }
+    }
+}
+
+fn update_plugin(
+    pack: &Package,
+    local_changes: LocalChanges,
+    progress: &PackReporter,
+) -> (Result<HeadChange>, bool) {
+    let res = do_update(pack, local_changes, progress);
     let status = match res {
-        Err(Error::SkipLocal) | Err(Error::Git(_)) => true,
+        Err(Error::SkipLocal) | Err(Error::Git(_)) | Err(Error::LocalChanges(_)) => true,
         Err(_) => false,
         _ => true,
     };
     (res, status)
 }
 
-fn do_update(pack: &Package) -> Result<()> {
+fn do_update(
+    pack: &Package,
+    local_changes: LocalChanges,
+    progress: &PackReporter,
+) -> Result<HeadChange> {
     let path = pack.path();
-    if !path.is_dir() {
+    if pack.local {
+        Err(Error::SkipLocal)
+    } else if !path.is_dir() {
         Err(Error::plugin_not_installed(&pack.idname))
     } else {
-        pack.git_pull()
+        pack.git_pull(local_changes, progress)
     }
 }
@@ -17,10 +17,15 @@ pub enum Error {
     Editor,
     Build(String),
     PluginNotInstalled(String),
+    NotAPackage(String),
     NoPlugin,
     SkipLocal,
+    SkipPinned,
     PluginInstalled(String),
     PaconfigFile(String),
+    Snapshot(String),
+    LocalChanges(String),
+    Dependency(String),
     CopyDir(String),
     SaveYaml,
     LoadYaml,
@@ -42,6 +47,47 @@ impl Error {
     pub fn plugin_not_installed(s: &str) -> Error {
         Error::PluginNotInstalled(format!("{} not installed", s))
     }
+
+    pub fn not_a_package<T: AsRef<Path>>(s: T) -> Error {
+        Error::NotAPackage(format!(
+            "{:?} is not under pack/<category>/<start|opt>",
+            s.as_ref()
+        ))
+    }
+
+    pub fn local_changes(n: usize) -> Error {
+        Error::LocalChanges(format!(
+            "{} locally modified file(s), use --force to discard or --stash to keep them",
+            n
+        ))
+    }
+
+    pub fn locked_local_changes(n: usize) -> Error {
+        Error::LocalChanges(format!(
+            "{} locally modified file(s), commit or discard them to check out the locked commit",
+            n
+        ))
+    }
+
+    pub fn dependency_cycle(idnames: &[&str]) -> Error {
+        Error::Dependency(format!("Dependency cycle: {}", idnames.join(" -> ")))
+    }
+
+    pub fn required_by(idname: &str, by: &[&str]) -> Error {
+        Error::Dependency(format!(
+            "{} is required by {}, use --force to uninstall it anyway",
+            idname,
+            by.join(", ")
+        ))
+    }
+
+    pub fn snapshot_not_found(s: &str) -> Error {
+        Error::Snapshot(format!("Can not find snapshot {}", s))
+    }
+
+    pub fn restore_failed(idnames: &[String]) -> Error {
+        Error::Snapshot(format!("Fail to restore {}", idnames.join(", ")))
+    }
 }
 
 impl From<io::Error> for Error {
@@ -92,13 +138,18 @@ impl fmt::Display for Error {
             Error::Editor => write!(f, "Can not open editor"),
             Error::NoPlugin => write!(f, "Can not find such plugin"),
             Error::SkipLocal => write!(f, "Local plugin. Skipping"),
-            Error::Io(ref e) => write!(f, "{}", e.to_string()),
+            Error::SkipPinned => write!(f, "Pinned to a commit. Skipping"),
+            Error::Io(ref e) => write!(f, "{}", e),
             Error::Build(ref s)
             | Error::Git(ref s)
             | Error::CopyDir(ref s)
             | Error::PluginInstalled(ref s)
             | Error::PluginNotInstalled(ref s)
-            | Error::PaconfigFile(ref s) => write!(f, "{}", s),
+            | Error::NotAPackage(ref s)
+            | Error::PaconfigFile(ref s)
+            | Error::Snapshot(ref s)
+            | Error::LocalChanges(ref s)
+            | Error::Dependency(ref s) => write!(f, "{}", s),
         }
         // write!(f, "{}", self.description())
     }
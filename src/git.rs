@@ -1,40 +1,338 @@
+use crate::report::{PackReporter, Phase, Transfer};
 use crate::{Error, Result};
 
-use git2::{self, Repository};
+use git2::{self, Oid, Repository};
+use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Commits HEAD pointed to before and after syncing a repo
+#[derive(Debug, Clone, Copy)]
+pub struct HeadChange {
+    /// None if the repo was freshly cloned
+    pub old: Option<Oid>,
+    pub new: Oid,
+}
+
+impl HeadChange {
+    pub fn is_changed(&self) -> bool {
+        self.old != Some(self.new)
+    }
+}
+
+/// What to do with local modifications in the working tree of a repo
+/// before syncing it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocalChanges {
+    /// Fail with `Error::LocalChanges`
+    Refuse,
+    Discard,
+    /// Save them with `git stash`
+    Stash,
+}
+
+/// State of a local repo
+#[derive(Debug, Clone)]
+pub struct RepoStatus {
+    /// Paths of tracked files changed in the working tree or index. These are
+    /// discarded when the repo is synced.
+    pub modified: Vec<String>,
+    /// Branch HEAD points to, None if HEAD is detached
+    pub branch: Option<String>,
+    pub head: Oid,
+    /// Commit the configured revision resolves to locally, if any
+    pub revision: Option<Oid>,
+    /// Url of the `origin` remote, or of the last fetch if there is none
+    pub remote_url: Option<String>,
+}
+
+/// Private keys tried in order after the ssh-agent, relative to `~/.ssh`
+const SSH_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Callbacks supplying credentials for private remotes: ssh-agent then the
+/// keys in `~/.ssh` for SSH remotes, git credential helpers for HTTPS ones.
+fn auth_callbacks<'a>() -> git2::RemoteCallbacks<'a> {
+    let mut keys = dirs::home_dir()
+        .map(|h| {
+            SSH_KEYS
+                .iter()
+                .map(|k| h.join(".ssh").join(k))
+                .filter(|k| k.is_file())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter();
+    let mut tried_agent = false;
+    let mut tried_helper = false;
+
+    // called again after each rejected credential, so try the next one
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        let user = username.unwrap_or("git");
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(user);
+        }
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            if !tried_agent {
+                tried_agent = true;
+                if let Ok(cred) = git2::Cred::ssh_key_from_agent(user) {
+                    return Ok(cred);
+                }
+            }
+            if let Some(key) = keys.next() {
+                return git2::Cred::ssh_key(user, None, &key, None);
+            }
+        } else if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) && !tried_helper {
+            tried_helper = true;
+            let config = git2::Config::open_default()?;
+            return git2::Cred::credential_helper(&config, url, username);
+        }
+        Err(git2::Error::from_str(&format!(
+            "Authentication failed for {}",
+            url
+        )))
+    });
+    callbacks
+}
+
+/// Fetch options authenticating with `auth_callbacks` and forwarding the
+/// transfer progress and the messages of the remote to `progress`.
+fn fetch_options<'a>(progress: &'a PackReporter) -> git2::FetchOptions<'a> {
+    let mut callbacks = auth_callbacks();
+    callbacks
+        .transfer_progress(move |stats| {
+            progress.transfer(&Transfer::from(stats));
+            true
+        })
+        .sideband_progress(move |data| {
+            progress.remote_message(data);
+            true
+        });
 
-/// Fetch from a remote repo (branches and tags). Does not change working tree.
-/// Returns the default remote branch.
-fn fetch(repo: &Repository, remote: &str) -> Result<String> {
     let mut opts = git2::FetchOptions::new();
-    opts.download_tags(git2::AutotagOption::All)
-        .update_fetchhead(true);
+    opts.remote_callbacks(callbacks);
+    opts
+}
+
+/// Fetch `refname` (a branch or tag, or the default branch if None) from a
+/// remote repo. Falls back to fetching every branch and tag if `refname` is
+/// not a branch or tag of the remote, e.g. a commit hash. Does not change
+/// working tree. Returns the reference to checkout.
+fn fetch(
+    repo: &Repository,
+    remote: &str,
+    refname: Option<String>,
+    progress: &PackReporter,
+) -> Result<String> {
+    let mut remote = repo.remote_anonymous(remote)?;
+    let mut opts = fetch_options(progress);
+    opts.update_fetchhead(true);
+
+    if let Some((name, _)) = find_remote_ref(&mut remote, refname.as_deref())? {
+        // tags pointing into the fetched history are still downloaded
+        opts.download_tags(git2::AutotagOption::Auto);
+        remote.fetch(&[&format!("+{0}:{0}", name)], Some(&mut opts), None)?;
+        return Ok(name);
+    }
 
     // fetch branches and tags
+    opts.download_tags(git2::AutotagOption::All);
     let refspec = ["refs/heads/*:refs/heads/*", "refs/tags/*:refs/tags/*"];
-    let mut remote = repo.remote_anonymous(&remote)?;
     remote.fetch(&refspec, Some(&mut opts), None)?;
+    refname.ok_or_else(|| Error::Git("Remote has no default branch".to_string()))
+}
+
+/// Find `refname` (a branch or tag, or the default branch if None) among the
+/// refs of the remote. Returns the full name of the ref and the object it
+/// points to, or None if `refname` is not a branch or tag, e.g. a commit hash.
+fn find_remote_ref(
+    remote: &mut git2::Remote,
+    refname: Option<&str>,
+) -> Result<Option<(String, Oid)>> {
+    // disconnects when dropped
+    let conn = remote.connect_auth(git2::Direction::Fetch, Some(auth_callbacks()), None)?;
 
-    remote
-        .default_branch()?
-        .as_str()
-        .ok_or_else(|| Error::Git(
-            "Default branch name is invalid utf-8".to_string(),
-        ))
-        // s is of the form "refs/heads/master" so split and use "master" only
-        .map(|s| s.to_string())
+    let wanted = match refname {
+        Some(r) => vec![
+            r.to_string(),
+            format!("refs/heads/{}", r),
+            format!("refs/tags/{}", r),
+        ],
+        None => vec![conn
+            .default_branch()?
+            .as_str()
+            .ok_or_else(|| Error::Git("Default branch name is invalid utf-8".to_string()))?
+            .to_string()],
+    };
+    let head = conn
+        .list()?
+        .iter()
+        .find(|h| wanted.iter().any(|w| w == h.name()))
+        .map(|h| (h.name().to_string(), h.oid()));
+    Ok(head)
+}
+
+/// Fetch `refspec` keeping only the last `depth` commits of history. This
+/// runs `git fetch` since libgit2 cannot create shallow repos, so credentials
+/// come from git's own configuration. git never prompts for them, as that
+/// would hang behind the progress output.
+fn fetch_shallow(
+    repo: &Repository,
+    remote: &str,
+    refspec: &str,
+    depth: u32,
+    progress: &PackReporter,
+) -> Result<()> {
+    let mut cmd = process::Command::new("git");
+    cmd.arg("--git-dir")
+        .arg(repo.path())
+        .args(["fetch", "--progress", "--no-tags", "--update-head-ok"])
+        .arg(format!("--depth={}", depth))
+        .arg("--")
+        .arg(remote)
+        .arg(refspec)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped());
+    if env::var_os("GIT_SSH_COMMAND").is_none() {
+        cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+    }
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::Git(
+                "git is not found in PATH, it is needed to clone with depth".to_string(),
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // forward the progress of git while keeping the output for errors
+    let mut output = Vec::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let mut buf = [0; 1024];
+        loop {
+            let n = stderr.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            progress.remote_message(&buf[..n]);
+            output.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    if child.wait()?.success() {
+        Ok(())
+    } else {
+        let output = String::from_utf8_lossy(&output);
+        let msg = output
+            .split(['\r', '\n'])
+            .rfind(|l| !l.trim().is_empty())
+            .unwrap_or("git fetch failed");
+        Err(Error::Git(msg.trim().to_string()))
+    }
+}
+
+/// Shallow fetch of `refname` (a branch, tag or full commit hash, or the
+/// default branch if None). Branches and tags are stored under the same name
+/// locally. Returns the name to checkout.
+fn fetch_rev_shallow(
+    repo: &Repository,
+    remote: &str,
+    refname: Option<String>,
+    depth: u32,
+    progress: &PackReporter,
+) -> Result<String> {
+    let mut anon = repo.remote_anonymous(remote)?;
+    match find_remote_ref(&mut anon, refname.as_deref())? {
+        Some((name, _)) => {
+            fetch_shallow(repo, remote, &format!("+{0}:{0}", name), depth, progress)?;
+            Ok(name)
+        }
+        None => {
+            // not a branch or tag, so it should be a commit
+            let rev =
+                refname.ok_or_else(|| Error::Git("Remote has no default branch".to_string()))?;
+            let rev = full_hash(repo, &rev)?;
+            fetch_shallow(repo, remote, &rev, depth, progress)?;
+            Ok(rev)
+        }
+    }
+}
+
+/// Full hash of the commit `rev`, as a shallow fetch of a commit needs it.
+/// Abbreviated hashes can only be resolved from commits already in the repo.
+fn full_hash(repo: &Repository, rev: &str) -> Result<String> {
+    if rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(rev.to_string());
+    }
+    repo.revparse_single(rev)
+        .and_then(|o| o.peel_to_commit())
+        .map(|c| c.id().to_string())
+        .map_err(|_| {
+            Error::Git(format!(
+                "{} is not a branch, tag or full commit hash, as needed with depth",
+                rev
+            ))
+        })
+}
+
+/// Find the commit that `refname` (a branch or tag, or the default branch if
+/// None) points to on the remote and download it, without updating any local
+/// ref. Returns None if `refname` is not a branch or tag of the remote, e.g.
+/// a commit hash.
+fn fetch_remote_head(
+    repo: &Repository,
+    remote_url: &str,
+    refname: Option<&str>,
+    depth: Option<u32>,
+    progress: &PackReporter,
+) -> Result<Option<Oid>> {
+    let mut remote = repo.remote_anonymous(remote_url)?;
+    match find_remote_ref(&mut remote, refname)? {
+        Some((name, oid)) => {
+            // no destination in refspec, so only FETCH_HEAD is updated
+            match depth {
+                Some(d) => fetch_shallow(repo, remote_url, &name, d, progress)?,
+                None => remote.fetch(&[&name], Some(&mut fetch_options(progress)), None)?,
+            }
+            // tags may point to tag objects instead of commits
+            Ok(Some(repo.find_object(oid, None)?.peel_to_commit()?.id()))
+        }
+        None => Ok(None),
+    }
 }
 
 /// Fetch changes from remote for a local repo, discard changes in working tree,
 /// checkout the given reference (or master if its None) and update submodules.
-fn sync_repo(repo: &Repository, remote: &str, refname: Option<String>) -> Result<()> {
-    let default_branch = fetch(&repo, remote)?;
+/// Only the history needed for the reference is fetched if `depth` is given.
+fn sync_repo(
+    repo: &Repository,
+    remote: &str,
+    refname: Option<String>,
+    depth: Option<u32>,
+    progress: &PackReporter,
+) -> Result<()> {
+    let refname = match depth {
+        Some(d) => fetch_rev_shallow(repo, remote, refname, d, progress)?,
+        None => fetch(repo, remote, refname, progress)?,
+    };
+
+    progress.phase(Phase::CheckingOut);
+    checkout(repo, &refname)?;
+    progress.phase(Phase::Submodules);
+    update_submodules(repo)?;
+    Ok(())
+}
 
-    let refname = refname.unwrap_or(default_branch);
+/// Discard changes in working tree and checkout the given reference.
+fn checkout(repo: &Repository, refname: &str) -> Result<()> {
     // `object` will always point to a commit disregarding intermediate
     // refs. `gitref` will be this intermediate ref, if applicable.
-    let (object, gitref) = repo.revparse_ext(&refname)?;
+    let (object, gitref) = repo.revparse_ext(refname)?;
 
     let mut opts = git2::build::CheckoutBuilder::new();
     opts.force(); // discard changes to working tree
@@ -47,8 +345,6 @@ fn sync_repo(repo: &Repository, remote: &str, refname: Option<String>) -> Result
         Some(gref) => repo.set_head(gref.name().ok_or(Error::Format)?),
         None => repo.set_head_detached(object.id()),
     }?;
-
-    update_submodules(&repo)?;
     Ok(())
 }
 
@@ -58,7 +354,11 @@ fn update_submodules(repo: &Repository) -> Result<()> {
             if let Some("docs") = subm.name() {
                 continue;
             }
-            subm.update(true, None)?;
+            let mut fetch_opts = git2::FetchOptions::new();
+            fetch_opts.remote_callbacks(auth_callbacks());
+            let mut opts = git2::SubmoduleUpdateOptions::new();
+            opts.fetch(fetch_opts);
+            subm.update(true, Some(&mut opts))?;
             list.push(subm.open()?);
         }
         Ok(())
@@ -72,25 +372,347 @@ fn update_submodules(repo: &Repository) -> Result<()> {
     Ok(())
 }
 
+/// Paths with changes in the working tree or index, including untracked files.
+/// Untracked files, like the `doc/tags` written by helptags or build outputs,
+/// are left alone by the forced checkout, so only tracked files count.
+fn modified_paths(repo: &Repository) -> Result<Vec<String>> {
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+    let paths = repo
+        .statuses(Some(&mut opts))?
+        .iter()
+        .filter_map(|e| e.path().map(|p| p.to_string()))
+        .collect();
+    Ok(paths)
+}
+
+fn handle_local_changes(repo: &mut Repository, changes: LocalChanges) -> Result<()> {
+    if changes == LocalChanges::Discard {
+        return Ok(());
+    }
+    let modified = modified_paths(repo)?.len();
+    if modified == 0 {
+        return Ok(());
+    }
+    match changes {
+        LocalChanges::Stash => {
+            let sig = repo
+                .signature()
+                .or_else(|_| git2::Signature::now("pac", "pac@localhost"))?;
+            repo.stash_save(&sig, "pac: local changes before sync", None)?;
+            Ok(())
+        }
+        _ => Err(Error::local_changes(modified)),
+    }
+}
+
+/// libgit2 cannot walk the history across the boundary of a shallow repo, so
+/// the commits are not counted there.
+fn count_behind(repo: &Repository, change: &HeadChange) -> Result<Option<usize>> {
+    if repo.is_shallow() {
+        return Ok(None);
+    }
+    match change.old {
+        Some(old) => Ok(Some(repo.graph_ahead_behind(change.new, old)?.0)),
+        None => Ok(Some(0)),
+    }
+}
+
+fn head_commit(repo: &Repository) -> Result<Oid> {
+    Ok(repo.head()?.peel_to_commit()?.id())
+}
+
+/// Remote url and revision to track for a repo not cloned by pac: the url of
+/// `origin`, and the current branch unless it is the default branch of
+/// `origin`, or the commit HEAD points to if it is detached.
+pub fn origin_info(path: &Path) -> Result<(String, Option<String>)> {
+    let repo = Repository::open(path)?;
+    let url = repo
+        .find_remote("origin")
+        .ok()
+        .and_then(|r| r.url().map(|u| u.to_string()))
+        .ok_or_else(|| Error::Git("No origin remote".to_string()))?;
+
+    if repo.head_detached()? {
+        return Ok((url, Some(head_commit(&repo)?.to_string())));
+    }
+    let branch = repo.head()?.shorthand().map(|s| s.to_string());
+    // refs/remotes/origin/HEAD points to the default branch, if it is known
+    let default = repo
+        .find_reference("refs/remotes/origin/HEAD")
+        .ok()
+        .and_then(|r| r.symbolic_target().map(|t| t.to_string()));
+    let rev = match (branch, default) {
+        (Some(b), Some(d)) if d == format!("refs/remotes/origin/{}", b) => None,
+        (b, _) => b,
+    };
+    Ok((url, rev))
+}
+
+/// Whether `name` is a tag of the repo at `path`, false if it cannot be read.
+pub fn is_tag(path: &Path, name: &str) -> bool {
+    Repository::open(path)
+        .map(|r| r.find_reference(&format!("refs/tags/{}", name)).is_ok())
+        .unwrap_or(false)
+}
+
+/// Replace the `%H`, `%h`, `%s`, `%an` and `%ae` placeholders in `format`
+/// (same meaning as in `git log --format`) with the details of a commit.
+fn format_commit(commit: &git2::Commit, format: &str) -> Result<String> {
+    let short = commit.as_object().short_id()?;
+    let author = commit.author();
+    let fields = [
+        ("%H", commit.id().to_string()),
+        ("%h", short.as_str().unwrap_or_default().to_string()),
+        ("%s", commit.summary().unwrap_or_default().to_string()),
+        ("%an", author.name().unwrap_or_default().to_string()),
+        ("%ae", author.email().unwrap_or_default().to_string()),
+    ];
+    Ok(expand_placeholders(format, &fields))
+}
+
+fn expand_placeholders(format: &str, fields: &[(&str, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = format;
+    'outer: while !rest.is_empty() {
+        for (key, val) in fields {
+            if let Some(r) = rest.strip_prefix(key) {
+                out += val;
+                rest = r;
+                continue 'outer;
+            }
+        }
+        let mut chars = rest.chars();
+        out.extend(chars.next());
+        rest = chars.as_str();
+    }
+    out
+}
+
 /// Trait representing high level git operations on a repo
 pub trait GitRepo {
     /// Get (remote_url, local_path, reference) for cloning and updating repo
     fn clone_info(&self) -> (&str, PathBuf, Option<String>);
 
+    /// Number of commits of history to fetch, everything if None
+    fn clone_depth(&self) -> Option<u32>;
+
     /// Clone a remote repository and update submodules.
-    fn git_clone(&self) -> Result<()> {
+    fn git_clone(&self, progress: &PackReporter) -> Result<HeadChange> {
         let (remote, path, rev) = self.clone_info();
         let repo = git2::Repository::init(&path)?;
-        let result = sync_repo(&repo, remote, rev);
-        if result.is_err() {
-            fs::remove_dir_all(&path)?;
+        progress.phase(Phase::Cloning);
+        let result = sync_repo(&repo, remote, rev, self.clone_depth(), progress)
+            .and_then(|_| head_commit(&repo));
+        match result {
+            Ok(new) => Ok(HeadChange { old: None, new }),
+            Err(e) => {
+                fs::remove_dir_all(&path)?;
+                Err(e)
+            }
         }
-        result
     }
 
-    fn git_pull(&self) -> Result<()> {
+    /// Fetch from remote and checkout the reference, dealing with local
+    /// modifications as specified by `changes`.
+    fn git_pull(&self, changes: LocalChanges, progress: &PackReporter) -> Result<HeadChange> {
+        let (remote, path, rev) = self.clone_info();
+        let mut repo = Repository::open(&path)?;
+        handle_local_changes(&mut repo, changes)?;
+        let old = head_commit(&repo).ok();
+        progress.phase(Phase::Fetching);
+        sync_repo(&repo, remote, rev, self.clone_depth(), progress)?;
+        let new = head_commit(&repo)?;
+        Ok(HeadChange { old, new })
+    }
+
+    /// Checkout the reference in the local repo without fetching from remote,
+    /// dealing with local modifications as specified by `changes`. Falls back
+    /// to fetching if the reference cannot be found locally.
+    fn git_checkout(&self, changes: LocalChanges, progress: &PackReporter) -> Result<HeadChange> {
         let (remote, path, rev) = self.clone_info();
+        let mut repo = Repository::open(&path)?;
+        handle_local_changes(&mut repo, changes)?;
+        let old = head_commit(&repo).ok();
+        match rev {
+            Some(ref r) if repo.revparse_single(r).is_ok() => {
+                progress.phase(Phase::CheckingOut);
+                checkout(&repo, r)?;
+                progress.phase(Phase::Submodules);
+                update_submodules(&repo)?;
+            }
+            _ => {
+                progress.phase(Phase::Fetching);
+                sync_repo(&repo, remote, rev, self.clone_depth(), progress)?
+            }
+        }
+        let new = head_commit(&repo)?;
+        Ok(HeadChange { old, new })
+    }
+
+    /// Fetch the tracked reference without changing the local repo. The
+    /// returned change is the one `git_pull` would make. Fails with
+    /// `Error::SkipPinned` if the revision is not a branch or tag, as there is
+    /// nothing to compare a pinned commit with.
+    fn git_fetch_head(&self, progress: &PackReporter) -> Result<HeadChange> {
+        let (remote, path, rev) = self.clone_info();
+        let repo = Repository::open(&path)?;
+        let old = head_commit(&repo)?;
+        progress.phase(Phase::Fetching);
+        let new =
+            match fetch_remote_head(&repo, remote, rev.as_deref(), self.clone_depth(), progress)? {
+                Some(new) => new,
+                None if rev.is_some() => return Err(Error::SkipPinned),
+                None => old,
+            };
+        Ok(HeadChange {
+            old: Some(old),
+            new,
+        })
+    }
+
+    /// Number of commits reachable from `change.new` but not from `change.old`,
+    /// None if the repo is shallow.
+    fn commits_behind(&self, change: &HeadChange) -> Result<Option<usize>> {
+        let (_, path, _) = self.clone_info();
+        let repo = Repository::open(&path)?;
+        count_behind(&repo, change)
+    }
+
+    /// Inspect the local repo without changing it.
+    fn git_status(&self) -> Result<RepoStatus> {
+        let (_, path, rev) = self.clone_info();
+        let repo = Repository::open(&path)?;
+
+        let modified = modified_paths(&repo)?;
+
+        let head = repo.head()?;
+        let branch = if repo.head_detached()? {
+            None
+        } else {
+            head.shorthand().map(|s| s.to_string())
+        };
+        let head = head.peel_to_commit()?.id();
+
+        let revision = rev
+            .and_then(|r| repo.revparse_single(&r).ok())
+            .and_then(|o| o.peel_to_commit().ok())
+            .map(|c| c.id());
+
+        let mut remote_url = repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|r| r.url().map(|u| u.to_string()));
+        if remote_url.is_none() {
+            // repos cloned by pac fetch from an anonymous remote, so read the
+            // url from FETCH_HEAD lines of the form "<oid>\t\t<ref> of <url>"
+            remote_url = fs::read_to_string(repo.path().join("FETCH_HEAD"))
+                .ok()
+                .and_then(|s| {
+                    let line = s.lines().next()?;
+                    line.rsplit(" of ").next().map(|u| u.to_string())
+                });
+        }
+
+        Ok(RepoStatus {
+            modified,
+            branch,
+            head,
+            revision,
+            remote_url,
+        })
+    }
+
+    /// Get the commit currently checked out in the local repo.
+    fn head(&self) -> Result<Oid> {
+        let (_, path, _) = self.clone_info();
+        head_commit(&Repository::open(&path)?)
+    }
+
+    /// Commits reachable from `change.new` but not from `change.old`,
+    /// newest first, each rendered with `format`.
+    fn git_log(&self, change: &HeadChange, format: &str) -> Result<Vec<String>> {
+        let (_, path, _) = self.clone_info();
         let repo = Repository::open(&path)?;
-        sync_repo(&repo, remote, rev)
+        let mut log = Vec::new();
+        if repo.is_shallow() {
+            // parents of the oldest commits are missing, so revwalk fails.
+            // Follow the first parents until a missing commit instead.
+            let mut next = Some(change.new);
+            while let Some(oid) = next.filter(|o| Some(*o) != change.old) {
+                let commit = match repo.find_commit(oid) {
+                    Ok(c) => c,
+                    Err(_) => break,
+                };
+                log.push(format_commit(&commit, format)?);
+                next = commit.parent_id(0).ok();
+            }
+            return Ok(log);
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(change.new)?;
+        if let Some(old) = change.old {
+            revwalk.hide(old)?;
+        }
+
+        for oid in revwalk {
+            let oid = oid?;
+            let commit = repo.find_commit(oid)?;
+            log.push(format_commit(&commit, format)?);
+        }
+        Ok(log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_log_placeholders() {
+        let fields = [
+            ("%h", "abc1234".to_string()),
+            ("%s", "Fix 100%".to_string()),
+        ];
+        assert_eq!(expand_placeholders("%h %s", &fields), "abc1234 Fix 100%");
+        assert_eq!(expand_placeholders("* %s (%x)", &fields), "* Fix 100% (%x)");
+    }
+
+    #[test]
+    fn shallow_full_hash() {
+        let path = env::temp_dir().join(format!("pac-test-full-hash-{}", process::id()));
+        let repo = Repository::init(&path).unwrap();
+        let hash = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(full_hash(&repo, hash).unwrap(), hash);
+        // unknown abbreviated hashes cannot be fetched
+        assert!(full_hash(&repo, "0123456").is_err());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn shallow_count_behind() {
+        let path = env::temp_dir().join(format!("pac-test-behind-{}", process::id()));
+        let repo = Repository::init(&path).unwrap();
+        let sig = git2::Signature::now("pac", "pac@localhost").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let old = repo
+            .commit(Some("HEAD"), &sig, &sig, "one", &tree, &[])
+            .unwrap();
+        let parent = repo.find_commit(old).unwrap();
+        let new = repo
+            .commit(Some("HEAD"), &sig, &sig, "two", &tree, &[&parent])
+            .unwrap();
+        let change = HeadChange {
+            old: Some(old),
+            new,
+        };
+        assert_eq!(count_behind(&repo, &change).unwrap(), Some(1));
+        // the history past the shallow boundary is missing
+        fs::write(repo.path().join("shallow"), format!("{}\n", old)).unwrap();
+        assert_eq!(count_behind(&repo, &change).unwrap(), None);
+        fs::remove_dir_all(&path).unwrap();
     }
 }
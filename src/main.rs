@@ -1,4 +1,3 @@
-use clap::ArgMatches;
 use std::env;
 use std::io;
 
@@ -10,7 +9,9 @@ mod cmd;
 mod echo;
 mod error;
 mod git;
+mod json;
 mod package;
+mod report;
 mod task;
 
 pub use error::{Error, Result};
@@ -22,17 +23,32 @@ fn main() {
 
     let app_m = cli::build_cli().get_matches();
 
+    // only these subcommands print their results as JSON
+    if let (name, Some(m)) = app_m.subcommand() {
+        let json = ["list", "install", "update", "status", "import"];
+        if m.is_present("json") && !json.contains(&name) {
+            die_json!(true, "--json is not supported by {}", name);
+        }
+    }
+
     match app_m.subcommand() {
         ("list", Some(m)) => cmd::list::exec(m),
         ("install", Some(m)) => cmd::install::exec(m),
         ("uninstall", Some(m)) => cmd::uninstall::exec(m),
         ("move", Some(m)) => cmd::move_cmd::exec(m),
         ("update", Some(m)) => cmd::update::exec(m),
+        ("restore", Some(m)) => cmd::restore::exec(m),
+        ("outdated", Some(m)) => cmd::outdated::exec(m),
+        ("status", Some(m)) => cmd::status::exec(m),
+        ("clean", Some(m)) => cmd::clean::exec(m),
+        ("adopt", Some(m)) => cmd::adopt::exec(m),
+        ("import", Some(m)) => cmd::import::exec(m),
+        ("export", Some(m)) => cmd::export::exec(m),
         ("generate", Some(m)) => cmd::generate::exec(m),
         ("completions", Some(m)) => {
             let shell = m.value_of("SHELL").unwrap();
             cli::build_cli().gen_completions_to("pac", shell.parse().unwrap(), &mut io::stdout());
         }
-        _ => cmd::list::exec(&ArgMatches::default()),
+        _ => cmd::list::exec(&app_m),
     }
 }
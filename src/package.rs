@@ -1,25 +1,142 @@
 use crate::git::GitRepo;
+use crate::json::Json;
+use crate::utils::lua_string;
 use crate::{Error, Result};
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::env;
 use std::fmt;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::os::unix::fs as unix_fs;
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use lazy_static::lazy_static;
 use yaml_rust::yaml::Hash;
 use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
 
 const PAC_PLUGIN_FILENAME: &str = "_pac.vim";
+const PAC_LUA_PLUGIN_FILENAME: &str = "_pac.lua";
 const PAC_PLUGIN_HEADER: &str = "\" Generated by pac. DO NOT EDIT!
 
 scriptencoding utf-8
 
-function! s:do_cmd(cmd, bang, start, end, args)
+\" packages loaded by s:load and the stub commands and mappings of the others
+let s:loaded = {}
+let s:stubs = {}
+
+function! s:load(names)
+    for name in a:names
+        if has_key(s:loaded, name)
+            continue
+        endif
+        let s:loaded[name] = 1
+        \" remove the stubs so that the commands and mappings of the plugin replace them
+        let stubs = get(s:stubs, name, {'cmds': [], 'maps': []})
+        for cmd in stubs.cmds
+            execute 'silent! delcommand' cmd
+        endfor
+        for map in stubs.maps
+            for mode in ['n', 'x', 'i']
+                execute 'silent!' mode.'unmap' map
+            endfor
+        endfor
+        execute 'packadd' name
+    endfor
+endfunction
+
+function! s:do_cmd(names, cmd, bang, start, end, args)
+    call s:load(a:names)
     exec printf('%s%s%s %s', (a:start == a:end ? '' : (a:start.','.a:end)), a:cmd, a:bang, a:args)
 endfunction
+
+function! s:do_map(names, map, prefix)
+    call s:load(a:names)
+    let map = substitute(a:map, '\\c<leader>', escape(get(g:, 'mapleader', '\\'), '\\'), 'g')
+    call feedkeys(a:prefix.eval('\"'.escape(map, '\\\"<').'\"'))
+endfunction
+
+function! s:detect(file)
+    \" the filetypedetect autocommands have already run for the buffer
+    if exists('#filetypedetect')
+        execute 'doautocmd <nomodeline> filetypedetect BufRead' fnameescape(a:file)
+    endif
+endfunction
+";
+
+const PAC_LUA_PLUGIN_HEADER: &str = "-- Generated by pac. DO NOT EDIT!
+
+-- packages loaded by load and the stub commands and mappings of the others
+local loaded = {}
+local stubs = {}
+
+local function load(names)
+    for _, name in ipairs(names) do
+        if not loaded[name] then
+            loaded[name] = true
+            -- remove the stubs so that the commands and mappings of the plugin replace them
+            local s = stubs[name] or { cmds = {}, maps = {} }
+            for _, c in ipairs(s.cmds) do
+                pcall(vim.api.nvim_del_user_command, c)
+            end
+            for _, lhs in ipairs(s.maps) do
+                for _, mode in ipairs({ 'n', 'x', 'i' }) do
+                    pcall(vim.keymap.del, mode, lhs)
+                end
+            end
+            vim.cmd.packadd(name)
+        end
+    end
+end
+
+local function do_cmd(names, cmd, opts)
+    load(names)
+    local range = opts.line1 == opts.line2 and '' or (opts.line1 .. ',' .. opts.line2)
+    vim.cmd(string.format('%s%s%s %s', range, cmd, opts.bang and '!' or '', opts.args))
+end
+
+local cmd_opts = { nargs = '*', range = true, bang = true }
+
+local function do_map(names, lhs)
+    load(names)
+    vim.api.nvim_feedkeys(vim.api.nvim_replace_termcodes(lhs, true, true, true), 'm', false)
+end
+
+local function detect(buf)
+    -- the filetypedetect autocommands have already run for the buffer
+    if vim.fn.exists('#filetypedetect') == 1 then
+        vim.api.nvim_exec_autocmds('BufRead', {
+            group = 'filetypedetect',
+            buffer = buf,
+            modeline = false,
+        })
+    end
+end
+";
+
+/// `load` of the Lua hook in the Vim loader, calling `s:load` by its `<SNR>`
+/// name in `g:pac_load` as Lua cannot call script-local functions otherwise
+const LUA_VIM_LOAD: &str = "local load = vim.fn[vim.g.pac_load]
+";
+
+/// Loads the packages in `on_require[module]` when `module` or one of its
+/// submodules is required, before the runtime path is searched for it
+const LUA_REQUIRE_HOOK: &str = "local on_require = {}
+
+table.insert(package.loaders, 1, function(module)
+    for prefix, names in pairs(on_require) do
+        if module == prefix or vim.startswith(module, prefix .. '.') then
+            on_require[prefix] = nil
+            load(names)
+        end
+    end
+end)
 ";
 
 const PAC_CONFIG_HEADER: &[u8] = b"# vim: ft=yaml
@@ -28,6 +145,21 @@ const PAC_CONFIG_HEADER: &[u8] = b"# vim: ft=yaml
 
 ";
 
+/// Prefixes of remotes given as `<forge>:user/repo`
+const FORGES: &[(&str, &str)] = &[
+    ("github", "https://github.com/"),
+    ("gitlab", "https://gitlab.com/"),
+    ("codeberg", "https://codeberg.org/"),
+    ("sr.ht", "https://git.sr.ht/"),
+    ("bitbucket", "https://bitbucket.org/"),
+];
+
+const PAC_LOCK_HEADER: &[u8] = b"# vim: ft=yaml
+#
+# Generated by pac. DO NOT EDIT!
+
+";
+
 lazy_static! {
     static ref VIM_BASE_DIR: PathBuf = env::var("VIM_CONFIG_PATH")
         .map(PathBuf::from)
@@ -39,6 +171,15 @@ lazy_static! {
     static ref VIM_PLUGIN_DIR: PathBuf = (*VIM_BASE_DIR).join("plugin");
     static ref PAC_CONFIG_DIR: PathBuf = (*VIM_BASE_DIR).join(".pac");
     static ref PAC_CONFIG_FILE: PathBuf = (*PAC_CONFIG_DIR).join("paconfig.yaml");
+    static ref PAC_LOCK_FILE: PathBuf = (*PAC_CONFIG_DIR).join("paconfig.lock");
+    static ref PAC_SNAPSHOT_DIR: PathBuf = (*PAC_CONFIG_DIR).join("snapshots");
+    static ref PAC_SETTINGS_FILE: PathBuf = (*PAC_CONFIG_DIR).join("settings.yaml");
+    static ref SETTINGS: Settings = match fetch_settings() {
+        Ok(s) => s,
+        Err(e) => die!("Err: {}", e),
+    };
+    /// Process groups of the builds running with a timeout
+    static ref BUILD_GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
 }
 
 #[derive(Debug, Clone)]
@@ -50,20 +191,41 @@ pub struct Package {
     /// is username/repo. Arguments to install, update, move, etc
     /// will be the idname, *not* name.
     pub idname: String,
-    /// Remote url of the repo to git clone from
+    /// Remote url of the repo to git clone from, or path of a local plugin
     pub remote: String,
+    /// Whether `remote` is the path of a plugin on this machine, which is
+    /// symlinked instead of cloned and never synced
+    pub local: bool,
     /// The branch, tag, or commit to checkout described as a rev
     pub revision: Option<String>,
     /// Install package under pack/<category>/. Default value is "default"
     pub category: String,
     /// Whether to put this package under pack/*/opt. Default value is false.
     pub opt: bool,
-    /// Load this package on this command
-    pub load_command: Option<String>,
+    /// Load this package on these commands
+    pub load_commands: Vec<String>,
     /// Load this package for these types
     pub for_types: Vec<String>,
+    /// Load this package on these autocommand events, e.g. `InsertEnter`
+    pub on_event: Vec<String>,
+    /// Load this package on these key mappings, e.g. `<Plug>(easymotion-s)`
+    pub on_map: Vec<String>,
+    /// Load this package when a function with these prefixes is undefined,
+    /// e.g. `fzf#` for its autoload functions
+    pub on_func: Vec<String>,
+    /// Load this package when reading files matching these patterns
+    pub on_path: Vec<String>,
+    /// Load this package when these Lua modules or their submodules are
+    /// required, in Neovim
+    pub on_require: Vec<String>,
+    /// Packages to load before this one, as given to `pac install`
+    pub requires: Vec<String>,
+    /// Installed only as a requirement of another package
+    pub auto: bool,
     /// Build command for this package
     pub build_command: Option<String>,
+    /// Number of commits of history to fetch, everything if None
+    pub depth: Option<u32>,
 }
 
 impl Package {
@@ -72,19 +234,58 @@ impl Package {
             name: name.to_string(),
             idname: Self::idname_from_remote(remote),
             remote: remote.to_string(),
+            local: false,
             revision: None,
             category: category.to_string(),
             opt,
-            load_command: None,
+            load_commands: Vec::new(),
             for_types: Vec::new(),
+            on_event: Vec::new(),
+            on_map: Vec::new(),
+            on_func: Vec::new(),
+            on_path: Vec::new(),
+            on_require: Vec::new(),
+            requires: Vec::new(),
+            auto: false,
             build_command: None,
+            depth: None,
         }
     }
 
-    /// Get username/repo from a git remote
+    /// Get the idname of a package from its git remote: username/repo for
+    /// GitHub, host/username/repo for other hosts so that idnames stay unique
+    /// across them. Remotes without a host (e.g. file://) use username/repo.
     pub fn idname_from_remote(remote: &str) -> String {
-        let parts = remote.split('/').collect::<Vec<_>>();
-        parts[parts.len() - 2..].join("/")
+        let (host, path) = split_remote(remote);
+        match (host, path.strip_suffix(".git").unwrap_or(path)) {
+            ("github.com", path) => path.to_string(),
+            ("", path) => {
+                let parts = path.split('/').collect::<Vec<_>>();
+                parts[parts.len().saturating_sub(2)..].join("/")
+            }
+            (host, path) => format!("{}/{}", host, path),
+        }
+    }
+
+    /// Get the default name of the local directory from a git remote, the last
+    /// part of its path as is (a `.git` suffix is kept)
+    pub fn name_from_remote(remote: &str) -> String {
+        let (_, path) = split_remote(remote);
+        path.rsplit('/').next().unwrap_or(path).to_string()
+    }
+
+    /// idnames of the packages in `requires`
+    pub fn required_idnames(&self) -> Vec<String> {
+        self.requires
+            .iter()
+            .map(|r| Self::idname_from_remote(&SETTINGS.expand_remote(r)))
+            .collect()
+    }
+
+    /// Whether the package is hosted on GitHub, so that its idname is enough
+    /// for other plugin managers to find it
+    pub fn on_github(&self) -> bool {
+        !self.local && split_remote(&self.remote).0 == "github.com"
     }
 
     pub fn is_installed(&self) -> bool {
@@ -107,49 +308,65 @@ impl Package {
 
     /// Parse a Package from a single list item in `PAC_CONFIG_FILE`
     pub fn from_yaml(doc: &Yaml) -> Result<Package> {
+        let local = doc["local"].as_str().is_some();
         let remote = doc["remote"]
             .as_str()
+            .or_else(|| doc["local"].as_str())
             .map(|s| s.to_string())
             .ok_or(Error::Format)?;
 
         let name = doc["name"]
             .as_str()
-            .or_else(|| remote.rsplit('/').next())
             .map(|s| s.to_string())
-            .ok_or(Error::Format)?;
+            .unwrap_or_else(|| Self::name_from_remote(&remote));
 
         let category = doc["category"]
             .as_str()
             .map_or("default".to_string(), |s| s.to_string());
 
         let opt = doc["opt"].as_bool().unwrap_or(false);
+        let auto = doc["auto"].as_bool().unwrap_or(false);
 
         let get_val = |key: &str| doc[key].as_str().map(|s| s.to_string());
         let revision = get_val("rev");
-        let cmd = get_val("on");
         let build = get_val("build");
 
-        let types = match doc["for"].as_vec() {
-            Some(f) => {
-                let mut types = Vec::with_capacity(f.len());
-                for e in f {
-                    types.push(e.as_str().map(|s| s.to_string()).ok_or(Error::Format)?);
-                }
-                types
-            }
-            None => vec![],
+        let depth = match doc["depth"] {
+            Yaml::BadValue => None,
+            Yaml::Integer(d) if d > 0 => Some(u32::try_from(d).map_err(|_| Error::Format)?),
+            _ => return Err(Error::Format),
+        };
+
+        // list of strings, a single string is a list of one
+        let get_list = |key: &str| match doc[key] {
+            Yaml::BadValue => Ok(vec![]),
+            Yaml::String(ref s) => Ok(vec![s.clone()]),
+            Yaml::Array(ref a) => a
+                .iter()
+                .map(|e| e.as_str().map(|s| s.to_string()).ok_or(Error::Format))
+                .collect(),
+            _ => Err(Error::Format),
         };
 
         Ok(Package {
             name,
             idname: Self::idname_from_remote(&remote),
             remote,
+            local,
             revision,
             category,
             opt,
-            load_command: cmd,
-            for_types: types,
+            load_commands: get_list("on")?,
+            for_types: get_list("for")?,
+            on_event: get_list("on_event")?,
+            on_map: get_list("on_map")?,
+            on_func: get_list("on_func")?,
+            on_path: get_list("on_path")?,
+            on_require: get_list("on_require")?,
+            requires: get_list("requires")?,
+            auto,
             build_command: build,
+            depth,
         })
     }
 
@@ -163,9 +380,13 @@ impl Package {
             };
         }
 
-        yaml_insert!(remote);
+        if self.local {
+            doc.insert(Yaml::from_str("local"), Yaml::from_str(&self.remote));
+        } else {
+            yaml_insert!(remote);
+        }
 
-        if !self.remote.ends_with(&self.name) {
+        if self.name != Self::name_from_remote(&self.remote) {
             yaml_insert!(name);
         }
         if self.category != "default" {
@@ -174,6 +395,9 @@ impl Package {
         if self.opt {
             doc.insert(Yaml::from_str("opt"), Yaml::Boolean(self.opt));
         }
+        if self.auto {
+            doc.insert(Yaml::from_str("auto"), Yaml::Boolean(self.auto));
+        }
 
         macro_rules! yaml_opt_insert {
             ($key:ident,$member:ident) => {
@@ -184,55 +408,172 @@ impl Package {
         }
 
         yaml_opt_insert!(rev, revision);
-        yaml_opt_insert!(on, load_command);
         yaml_opt_insert!(build, build_command);
+        // a single command is written as a string, like older paconfigs
+        match self.load_commands.as_slice() {
+            [] => (),
+            [c] => {
+                doc.insert(Yaml::from_str("on"), Yaml::from_str(c));
+            }
+            cmds => {
+                let cmds = cmds.iter().map(|c| Yaml::from_str(c)).collect::<Vec<_>>();
+                doc.insert(Yaml::from_str("on"), Yaml::Array(cmds));
+            }
+        }
+        if let Some(d) = self.depth {
+            doc.insert(Yaml::from_str("depth"), Yaml::Integer(d.into()));
+        }
 
-        if !self.for_types.is_empty() {
-            let types = self
-                .for_types
-                .iter()
-                .map(|e| Yaml::from_str(e))
-                .collect::<Vec<Yaml>>();
-            doc.insert(Yaml::from_str("for"), Yaml::Array(types));
+        macro_rules! yaml_list_insert {
+            ($key:ident,$member:ident) => {
+                if !self.$member.is_empty() {
+                    let list = self
+                        .$member
+                        .iter()
+                        .map(|e| Yaml::from_str(e))
+                        .collect::<Vec<Yaml>>();
+                    doc.insert(Yaml::from_str(stringify!($key)), Yaml::Array(list));
+                }
+            };
         }
+
+        yaml_list_insert!(for, for_types);
+        yaml_list_insert!(on_event, on_event);
+        yaml_list_insert!(on_map, on_map);
+        yaml_list_insert!(on_func, on_func);
+        yaml_list_insert!(on_path, on_path);
+        yaml_list_insert!(on_require, on_require);
+        yaml_list_insert!(requires, requires);
         Yaml::Hash(doc)
     }
 
+    /// Convert Package to a JSON record for machine readable output
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("idname", Json::from(self.idname.as_str())),
+            ("name", Json::from(self.name.as_str())),
+            ("remote", Json::from(self.remote.as_str())),
+            ("local", Json::from(self.local)),
+            ("rev", Json::from(self.revision.clone())),
+            ("category", Json::from(self.category.as_str())),
+            ("opt", Json::from(self.opt)),
+            ("on", Json::from(self.load_commands.clone())),
+            ("for", Json::from(self.for_types.clone())),
+            ("on_event", Json::from(self.on_event.clone())),
+            ("on_map", Json::from(self.on_map.clone())),
+            ("on_func", Json::from(self.on_func.clone())),
+            ("on_path", Json::from(self.on_path.clone())),
+            ("on_require", Json::from(self.on_require.clone())),
+            ("requires", Json::from(self.requires.clone())),
+            ("auto", Json::from(self.auto)),
+            ("build", Json::from(self.build_command.clone())),
+            ("depth", Json::from(self.depth)),
+            (
+                "path",
+                Json::from(self.path().to_string_lossy().into_owned()),
+            ),
+            ("installed", Json::from(self.is_installed())),
+        ])
+    }
+
     /// Returns absolute path to directory where plugin can be installed
     pub fn path(&self) -> PathBuf {
-        if self.opt {
-            VIM_PACKAGE_DIR
-                .join(&self.category)
-                .join("opt")
-                .join(&self.name)
-        } else {
-            VIM_PACKAGE_DIR
-                .join(&self.category)
-                .join("start")
-                .join(&self.name)
+        let option = if self.opt { "opt" } else { "start" };
+        pack_path(&self.category, option, &self.name)
+    }
+
+    /// Path of a local plugin, with `~` expanded to the home directory
+    pub fn local_path(&self) -> PathBuf {
+        match (self.remote.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(&self.remote),
         }
     }
 
+    /// Symlink a local plugin into its package directory. Returns false if
+    /// it was already linked to the same path.
+    pub fn link_local(&self) -> Result<bool> {
+        let path = self.path();
+        let target = self.local_path();
+        if !target.is_dir() {
+            return Err(Error::PluginNotInstalled(format!(
+                "Local plugin {:?} not found",
+                target
+            )));
+        }
+        if let Ok(meta) = fs::symlink_metadata(&path) {
+            if !meta.file_type().is_symlink() {
+                return Err(Error::plugin_installed(&path));
+            }
+            if fs::read_link(&path)? == target {
+                return Ok(false);
+            }
+            fs::remove_file(&path)?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        unix_fs::symlink(&target, &path)?;
+        Ok(true)
+    }
+
     /// Run the build command using `sh -c ...`
     ///
     /// # Errors
     ///
     /// If the build process returns a non zero exit status, an `Error::Build`
-    /// variant will be returned along with stderr.
+    /// variant will be returned along with stderr. The process and its
+    /// children are killed if it runs longer than the `build_timeout` setting.
     pub fn try_build(&self) -> Result<()> {
         if let Some(ref c) = self.build_command {
             let path = self.path();
-            let p = process::Command::new("sh")
-                .arg("-c")
+            let mut cmd = process::Command::new("sh");
+            cmd.arg("-c")
                 .arg(c)
-                .stdout(process::Stdio::piped())
+                .stdout(process::Stdio::null())
                 .stderr(process::Stdio::piped())
-                .current_dir(&path)
-                .spawn()?;
-            let output = p.wait_with_output()?;
-            if !output.status.success() {
-                let err = String::from_utf8(output.stderr)
-                    .unwrap_or_else(|_| String::from("No error output!"));
+                .current_dir(&path);
+            // with a timeout, in its own process group so that the processes it
+            // starts can be killed along with it. The group no longer gets the
+            // signals of the terminal, `signal_builds` forwards them.
+            if SETTINGS.build_timeout.is_some() {
+                cmd.process_group(0);
+            }
+            let mut p = cmd.spawn()?;
+
+            // read stderr meanwhile so that the build cannot block on a full pipe
+            let mut stderr = p.stderr.take().ok_or(Error::Build(String::new()))?;
+            let reader = thread::spawn(move || {
+                let mut out = Vec::new();
+                let _ = stderr.read_to_end(&mut out);
+                out
+            });
+
+            let status = match SETTINGS.build_timeout {
+                Some(secs) => {
+                    BUILD_GROUPS.lock().unwrap().push(p.id());
+                    let status = wait_timeout(&mut p, Duration::from_secs(secs));
+                    if let Ok(None) = status {
+                        // the group id is the pid of its leader
+                        unsafe { libc::kill(-(p.id() as libc::pid_t), libc::SIGKILL) };
+                        let _ = p.wait();
+                    }
+                    BUILD_GROUPS.lock().unwrap().retain(|&g| g != p.id());
+                    match status? {
+                        Some(s) => s,
+                        None => {
+                            return Err(Error::Build(format!("Timed out after {} seconds", secs)))
+                        }
+                    }
+                }
+                None => p.wait()?,
+            };
+            if !status.success() {
+                let err = reader
+                    .join()
+                    .ok()
+                    .and_then(|out| String::from_utf8(out).ok())
+                    .unwrap_or_else(|| String::from("No error output!"));
                 return Err(Error::Build(err));
             }
         }
@@ -240,18 +581,65 @@ impl Package {
     }
 }
 
+/// Send `signal` to the builds running in their own process group, which do
+/// not get the signals of the terminal.
+pub fn signal_builds(signal: i32) {
+    for &g in BUILD_GROUPS.lock().unwrap().iter() {
+        unsafe { libc::kill(-(g as libc::pid_t), signal) };
+    }
+}
+
+/// Split a git remote into its host (without user, empty for local paths)
+/// and path (without trailing `/`). Handles urls like
+/// `https://host/user/repo` and scp-like remotes like `git@host:user/repo.git`.
+fn split_remote(remote: &str) -> (&str, &str) {
+    let (authority, path) = match remote.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        None => match remote.split_once(':') {
+            // a colon after a slash is part of a local path
+            Some((host, path)) if !host.contains('/') => (host, path),
+            _ => ("", remote),
+        },
+    };
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    (host, path.trim_matches('/'))
+}
+
+/// Wait for the process to exit, returning None if it is still running after
+/// `timeout`.
+fn wait_timeout(
+    child: &mut process::Child,
+    timeout: Duration,
+) -> Result<Option<process::ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
 impl GitRepo for Package {
     fn clone_info(&self) -> (&str, PathBuf, Option<String>) {
         (&self.remote, self.path(), self.revision.clone())
     }
+
+    fn clone_depth(&self) -> Option<u32> {
+        SETTINGS.clone_depth(self)
+    }
 }
 
 impl fmt::Display for Package {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let name = if self.opt { "opt" } else { "start" };
-        let on = match self.load_command {
-            Some(ref c) => format!(" [Load on `{}`]", c),
-            None => "".to_string(),
+        let on = if !self.load_commands.is_empty() {
+            format!(" [Load on `{}`]", self.load_commands.join("`,`"))
+        } else {
+            "".to_string()
         };
 
         let types = if !self.for_types.is_empty() {
@@ -260,10 +648,28 @@ impl fmt::Display for Package {
         } else {
             "".to_string()
         };
+        let triggers = [
+            ("Events", &self.on_event),
+            ("Maps", &self.on_map),
+            ("Funcs", &self.on_func),
+            ("Paths", &self.on_path),
+            ("Modules", &self.on_require),
+            ("Requires", &self.requires),
+        ]
+        .iter()
+        .filter(|(_, t)| !t.is_empty())
+        .map(|(label, t)| format!(" [{} {}]", label, t.join(",")))
+        .collect::<String>();
+        let local = if self.local {
+            format!(" [Local {}]", &self.remote)
+        } else {
+            "".to_string()
+        };
+        let auto = if self.auto { " [Auto]" } else { "" };
         write!(
             f,
-            "{} => pack/{}/{}{}{}",
-            &self.idname, &self.category, name, on, types
+            "{} => pack/{}/{}{}{}{}{}{}",
+            &self.idname, &self.category, name, on, types, triggers, local, auto
         )
     }
 }
@@ -316,42 +722,718 @@ pub fn save(packs: Vec<Package>) -> Result<()> {
     Ok(())
 }
 
-/// Update `_pac.vim` file in plugin directory.
+/// Returns the commits recorded in the lockfile, keyed by idname.
+pub fn fetch_lock() -> Result<HashMap<String, String>> {
+    if PAC_LOCK_FILE.is_file() {
+        fetch_from_lockfile(&*PAC_LOCK_FILE)
+            .map_err(|e| Error::PaconfigFile(format!("Fail to parse paconfig.lock: {}", e)))
+    } else {
+        Ok(HashMap::new())
+    }
+}
+
+fn fetch_from_lockfile<P: AsRef<Path>>(lockfile: P) -> Result<HashMap<String, String>> {
+    let mut data = String::new();
+    File::open(lockfile.as_ref())?.read_to_string(&mut data)?;
+    let docs = YamlLoader::load_from_str(&data)?;
+
+    let mut ret = HashMap::new();
+    if !docs.is_empty() {
+        if let Some(doc) = docs[0].as_hash() {
+            for (idname, commit) in doc {
+                let idname = idname.as_str().ok_or(Error::Format)?;
+                let commit = commit.as_str().ok_or(Error::Format)?;
+                ret.insert(migrate_idname(idname), commit.to_string());
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Idnames of remotes ending in `.git` used to keep the suffix, e.g.
+/// `u/foo.vim.git`. Entries recorded under such idnames are read under the
+/// current one, which never ends in `.git`.
+fn migrate_idname(idname: &str) -> String {
+    idname.strip_suffix(".git").unwrap_or(idname).to_string()
+}
+
+/// Write out the lockfile recording the commit checked out for each package.
+/// Packages that are not installed keep their previously locked commit, if any.
+pub fn save_lock(packs: &[Package]) -> Result<()> {
+    let locked = fetch_lock()?;
+
+    let mut doc = Hash::new();
+    for p in packs.iter().filter(|p| !p.local) {
+        let commit = match p.head() {
+            Ok(oid) => oid.to_string(),
+            Err(_) => match locked.get(&p.idname) {
+                Some(c) => c.clone(),
+                None => continue,
+            },
+        };
+        doc.insert(Yaml::from_str(&p.idname), Yaml::String(commit));
+    }
+
+    let mut out = String::new();
+    {
+        let mut emitter = YamlEmitter::new(&mut out);
+        emitter.dump(&Yaml::Hash(doc))?;
+    }
+    if !PAC_CONFIG_DIR.is_dir() {
+        fs::create_dir_all(&*PAC_CONFIG_DIR)?;
+    }
+    let mut f = File::create(&*PAC_LOCK_FILE)?;
+    f.write_all(PAC_LOCK_HEADER)?;
+    f.write_all(out.as_bytes())?;
+    Ok(())
+}
+
+/// Commits of a package before and after an update run
+#[derive(Debug, Clone)]
+pub struct SnapshotEntry {
+    pub idname: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// Returns the names of all saved snapshots, oldest first.
+pub fn list_snapshots() -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    if PAC_SNAPSHOT_DIR.is_dir() {
+        for e in PAC_SNAPSHOT_DIR.read_dir()?.flatten() {
+            let path = e.path();
+            if path.extension().is_some_and(|ext| ext == "yaml") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the entries of the named snapshot, or of the latest one if `name`
+/// is None.
+pub fn fetch_snapshot(name: Option<&str>) -> Result<Vec<SnapshotEntry>> {
+    let name = match name {
+        Some(n) => n.to_string(),
+        None => list_snapshots()?
+            .pop()
+            .ok_or_else(|| Error::snapshot_not_found("latest"))?,
+    };
+    let path = PAC_SNAPSHOT_DIR.join(format!("{}.yaml", name));
+    if !path.is_file() {
+        return Err(Error::snapshot_not_found(&name));
+    }
+
+    let mut data = String::new();
+    File::open(&path)?.read_to_string(&mut data)?;
+    let docs = YamlLoader::load_from_str(&data)?;
+
+    let mut ret = Vec::new();
+    if !docs.is_empty() {
+        if let Some(doc) = docs[0].as_hash() {
+            for (idname, commits) in doc {
+                let get_val = |key: &str| {
+                    commits[key]
+                        .as_str()
+                        .map(|s| s.to_string())
+                        .ok_or(Error::Format)
+                };
+                ret.push(SnapshotEntry {
+                    idname: migrate_idname(idname.as_str().ok_or(Error::Format)?),
+                    before: get_val("before")?,
+                    after: get_val("after")?,
+                });
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Write out a new snapshot under `PAC_SNAPSHOT_DIR` named after the current
+/// time, with a zero-padded suffix if a snapshot of the same name exists, and
+/// return its name.
+pub fn save_snapshot(entries: &[SnapshotEntry]) -> Result<String> {
+    let mut doc = Hash::new();
+    for e in entries {
+        let mut commits = Hash::new();
+        commits.insert(Yaml::from_str("before"), Yaml::String(e.before.clone()));
+        commits.insert(Yaml::from_str("after"), Yaml::String(e.after.clone()));
+        doc.insert(Yaml::from_str(&e.idname), Yaml::Hash(commits));
+    }
+
+    let mut out = String::new();
+    {
+        let mut emitter = YamlEmitter::new(&mut out);
+        emitter.dump(&Yaml::Hash(doc))?;
+    }
+    if !PAC_SNAPSHOT_DIR.is_dir() {
+        fs::create_dir_all(&*PAC_SNAPSHOT_DIR)?;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // sorts in the order the snapshots were taken
+    let stamp = format!("{}.{:09}", now.as_secs(), now.subsec_nanos());
+    let mut name = stamp.clone();
+    let mut n = 0;
+    let mut f = loop {
+        let path = PAC_SNAPSHOT_DIR.join(format!("{}.yaml", name));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+        {
+            Ok(f) => break f,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                n += 1;
+                name = format!("{}-{:03}", stamp, n);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    f.write_all(PAC_LOCK_HEADER)?;
+    f.write_all(out.as_bytes())?;
+    Ok(name)
+}
+
+/// Defaults read from `PAC_SETTINGS_FILE`. Command line arguments take
+/// precedence over these.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Number of plugins synced concurrently, the number of CPUs if None
+    pub threads: Option<usize>,
+    /// Category new plugins are installed under
+    pub category: String,
+    /// Prefix of the remote url of plugins given as `user/repo`
+    pub host: String,
+    /// Clone depth of plugins without a `depth` key, full history if None
+    pub depth: Option<u32>,
+    /// Seconds after which build commands are killed, no limit if None
+    pub build_timeout: Option<u64>,
+    /// User defined `<alias>:user/repo` prefixes, taking precedence over `FORGES`
+    pub aliases: HashMap<String, String>,
+    /// Language of the generated plugin lazy loading the opt packages
+    pub loader: Loader,
+}
+
+/// How the generated plugin under `plugin/` loads the opt packages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loader {
+    /// `_pac.vim` with `command!` and `autocmd`
+    Vim,
+    /// `_pac.lua` with the Neovim API, faster and usable from Lua configs
+    Lua,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            threads: None,
+            category: "default".to_string(),
+            host: "https://github.com/".to_string(),
+            depth: None,
+            build_timeout: None,
+            aliases: HashMap::new(),
+            loader: Loader::Vim,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_yaml(doc: &Yaml) -> Result<Settings> {
+        let mut settings = Settings::default();
+        if let Yaml::BadValue | Yaml::Null = *doc {
+            return Ok(settings);
+        }
+        doc.as_hash().ok_or(Error::Format)?;
+
+        // positive integer or None if the key is missing
+        let get_num = |key: &str| match doc[key] {
+            Yaml::BadValue => Ok(None),
+            Yaml::Integer(n) if n > 0 => Ok(Some(n as u64)),
+            _ => Err(Error::Format),
+        };
+        let get_str = |key: &str| match doc[key] {
+            Yaml::BadValue => Ok(None),
+            Yaml::String(ref s) => Ok(Some(s.clone())),
+            _ => Err(Error::Format),
+        };
+
+        settings.threads = get_num("threads")?.map(|n| n as usize);
+        settings.depth = get_num("depth")?
+            .map(|n| u32::try_from(n).map_err(|_| Error::Format))
+            .transpose()?;
+        settings.build_timeout = get_num("build_timeout")?;
+        if let Some(c) = get_str("category")? {
+            settings.category = c;
+        }
+        if let Some(h) = get_str("host")? {
+            settings.host = with_trailing_slash(h);
+        }
+        settings.loader = match get_str("loader")?.as_deref() {
+            None | Some("vim") => Loader::Vim,
+            Some("lua") => Loader::Lua,
+            Some(_) => return Err(Error::Format),
+        };
+        match doc["aliases"] {
+            Yaml::BadValue => (),
+            Yaml::Hash(ref aliases) => {
+                for (alias, url) in aliases {
+                    let alias = alias.as_str().ok_or(Error::Format)?;
+                    let url = url.as_str().ok_or(Error::Format)?;
+                    settings
+                        .aliases
+                        .insert(alias.to_string(), with_trailing_slash(url.to_string()));
+                }
+            }
+            _ => return Err(Error::Format),
+        }
+        Ok(settings)
+    }
+
+    /// Get the remote url of a plugin given on the command line, either as
+    /// an url, `<forge or alias>:user/repo` or `user/repo` on the default host.
+    pub fn expand_remote(&self, plug: &str) -> String {
+        // urls and scp-like remotes (git@host:user/repo)
+        if plug.contains("://") || plug.split_once(':').is_some_and(|(p, _)| p.contains('@')) {
+            return plug.to_string();
+        }
+        if let Some((prefix, repo)) = plug.split_once(':') {
+            let url = self
+                .aliases
+                .get(prefix)
+                .map(|u| u.as_str())
+                .or_else(|| FORGES.iter().find(|f| f.0 == prefix).map(|f| f.1));
+            if let Some(url) = url {
+                return format!("{}{}", url, repo);
+            }
+        }
+        format!("{}{}", self.host, plug)
+    }
+
+    /// Get the idname of a plugin given on the command line, either as an
+    /// idname or as accepted by install (e.g. `gitlab:user/repo`).
+    pub fn resolve_idname(&self, packs: &[Package], plug: &str) -> String {
+        if packs.iter().any(|p| p.idname == plug) {
+            plug.to_string()
+        } else {
+            Package::idname_from_remote(&self.expand_remote(plug))
+        }
+    }
+
+    /// Clone depth of a package, its own or the default one
+    pub fn clone_depth(&self, pack: &Package) -> Option<u32> {
+        pack.depth.or(self.depth)
+    }
+
+    /// Number of threads to use if not given on the command line
+    pub fn threads(&self) -> usize {
+        self.threads.unwrap_or_else(num_cpus::get)
+    }
+}
+
+fn with_trailing_slash(mut url: String) -> String {
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    url
+}
+
+/// Returns the settings read once from `PAC_SETTINGS_FILE` at startup.
+pub fn settings() -> &'static Settings {
+    &SETTINGS
+}
+
+fn fetch_settings() -> Result<Settings> {
+    if !PAC_SETTINGS_FILE.is_file() {
+        return Ok(Settings::default());
+    }
+    let mut data = String::new();
+    File::open(&*PAC_SETTINGS_FILE)?.read_to_string(&mut data)?;
+    let docs = YamlLoader::load_from_str(&data)
+        .map_err(|e| Error::PaconfigFile(format!("Fail to parse settings.yaml: {}", e)))?;
+    match docs.first() {
+        Some(doc) => Settings::from_yaml(doc)
+            .map_err(|e| Error::PaconfigFile(format!("Fail to parse settings.yaml: {}", e))),
+        None => Ok(Settings::default()),
+    }
+}
+
+/// Returns absolute path of a package directory, `category/option/name`
+/// under the `pack` directory.
+pub fn pack_path(category: &str, option: &str, name: &str) -> PathBuf {
+    VIM_PACKAGE_DIR.join(category).join(option).join(name)
+}
+
+/// Returns the directories under `pack` which are not the path of any of
+/// `packs`, sorted.
+pub fn detached_paths(packs: &[Package]) -> Result<Vec<PathBuf>> {
+    let tracked = packs.iter().map(|p| p.path()).collect::<Vec<_>>();
+    let mut detached = Vec::new();
+    walk_packs(&None, false, false, |cate, option, name| {
+        let path = pack_path(cate, option, name);
+        if !tracked.contains(&path) {
+            detached.push(path);
+        }
+    })?;
+    detached.sort();
+    Ok(detached)
+}
+
+/// Returns the packages required by `pack`, directly or not, each after its
+/// own requirements. Requirements not in `packs` are left out.
+pub fn requirements<'a>(pack: &'a Package, packs: &'a [Package]) -> Result<Vec<&'a Package>> {
+    fn visit<'a>(
+        pack: &'a Package,
+        packs: &'a [Package],
+        path: &mut Vec<&'a str>,
+        order: &mut Vec<&'a Package>,
+    ) -> Result<()> {
+        for idname in pack.required_idnames() {
+            let dep = match packs.iter().find(|p| p.idname == idname) {
+                Some(d) => d,
+                None => continue,
+            };
+            if let Some(i) = path.iter().position(|p| *p == dep.idname) {
+                let mut cycle = path[i..].to_vec();
+                cycle.push(&dep.idname);
+                return Err(Error::dependency_cycle(&cycle));
+            }
+            if order.iter().any(|p| p.idname == dep.idname) {
+                continue;
+            }
+            path.push(&dep.idname);
+            visit(dep, packs, path, order)?;
+            path.pop();
+            order.push(dep);
+        }
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    visit(pack, packs, &mut vec![pack.idname.as_str()], &mut order)?;
+    Ok(order)
+}
+
+/// Fail if the requirements of `packs` have a cycle.
+pub fn check_requires(packs: &[Package]) -> Result<()> {
+    for pack in packs {
+        requirements(pack, packs)?;
+    }
+    Ok(())
+}
+
+/// Returns absolute path of the `pack` directory
+pub fn pack_dir() -> &'static Path {
+    &VIM_PACKAGE_DIR
+}
+
+/// Update `_pac.vim` (or `_pac.lua`) file in plugin directory, removing the
+/// one of the other loader so that packages are not set up twice.
 pub fn update_pac_plugin(packs: &[Package]) -> Result<()> {
     if !VIM_PLUGIN_DIR.is_dir() {
         fs::create_dir_all(&*VIM_PLUGIN_DIR)?;
     }
 
-    let mut f = File::create(VIM_PLUGIN_DIR.join(PAC_PLUGIN_FILENAME))?;
-    f.write_all(format!("{}\n\n", PAC_PLUGIN_HEADER).as_bytes())?;
+    if let Some(other) = other_pac_plugin() {
+        fs::remove_file(other)?;
+    }
+
+    let mut f = File::create(pac_plugin_path())?;
+    f.write_all(pac_plugin_content(packs)?.as_bytes())?;
+    Ok(())
+}
+
+/// Returns absolute path of the generated `_pac.vim` or `_pac.lua` file
+pub fn pac_plugin_path() -> PathBuf {
+    match SETTINGS.loader {
+        Loader::Vim => VIM_PLUGIN_DIR.join(PAC_PLUGIN_FILENAME),
+        Loader::Lua => VIM_PLUGIN_DIR.join(PAC_LUA_PLUGIN_FILENAME),
+    }
+}
+
+/// Returns the generated plugin of the loader not in use, if any
+fn other_pac_plugin() -> Option<PathBuf> {
+    let other = match SETTINGS.loader {
+        Loader::Vim => VIM_PLUGIN_DIR.join(PAC_LUA_PLUGIN_FILENAME),
+        Loader::Lua => VIM_PLUGIN_DIR.join(PAC_PLUGIN_FILENAME),
+    };
+    Some(other).filter(|p| p.is_file())
+}
+
+/// Whether the generated plugin does not load `packs` as it should, or the
+/// one of the other loader is left over.
+pub fn pac_plugin_stale(packs: &[Package]) -> Result<bool> {
+    let path = pac_plugin_path();
+    if path.is_file() {
+        Ok(fs::read_to_string(path)? != pac_plugin_content(packs)? || other_pac_plugin().is_some())
+    } else {
+        Ok(other_pac_plugin().is_some())
+    }
+}
+
+/// Returns the content of the generated plugin loading the packages. Fails
+/// if their requirements have a cycle.
+pub fn pac_plugin_content(packs: &[Package]) -> Result<String> {
+    match SETTINGS.loader {
+        Loader::Vim => vim_loader(packs),
+        Loader::Lua => lua_loader(packs),
+    }
+}
+
+/// Split an `on_event` trigger into its event and pattern, e.g. `User Foo`
+fn split_event(event: &str) -> (&str, &str) {
+    event.split_once(' ').unwrap_or((event, "*"))
+}
+
+/// Names of the packages to `packadd` to load `pack`: its opt requirements,
+/// which are not loaded yet, then itself
+fn load_names<'a>(pack: &'a Package, packs: &'a [Package]) -> Result<Vec<&'a str>> {
+    let mut names = requirements(pack, packs)?
+        .into_iter()
+        .filter(|p| p.opt)
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    names.push(&pack.name);
+    Ok(names)
+}
+
+/// Pattern of a `FuncUndefined` autocommand for an `on_func` prefix
+fn func_pattern(prefix: &str) -> String {
+    if prefix.ends_with('*') {
+        prefix.to_string()
+    } else {
+        format!("{}*", prefix)
+    }
+}
+
+fn vim_loader(packs: &[Package]) -> Result<String> {
+    let mut content = format!("{}\n\n", PAC_PLUGIN_HEADER);
 
     let mut plug_setup = String::new();
+    let mut on_require = String::new();
     for p in packs.iter() {
-        if let Some(ref c) = p.load_command {
+        let names = load_names(p, packs)?;
+        on_require += &lua_on_require(p, &names);
+        let names = format!(
+            "[{}]",
+            names
+                .iter()
+                .map(|n| format!("'{}'", n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let load = format!("call s:load({})", names);
+        if !p.load_commands.is_empty() || !p.on_map.is_empty() {
+            let list = |items: &[String]| {
+                items
+                    .iter()
+                    .map(|i| format!("'{}'", i.replace('\'', "''")))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
             plug_setup += &format!(
-                "command! -nargs=* -range -bang {cmd} packadd {repo} | \
-                 call s:do_cmd('{cmd}', \"<bang>\", <line1>, <line2>, <q-args>)\n\n",
+                "let s:stubs['{}'] = {{'cmds': [{}], 'maps': [{}]}}\n",
+                p.name,
+                list(&p.load_commands),
+                list(&p.on_map),
+            );
+        }
+        for c in &p.load_commands {
+            plug_setup += &format!(
+                "command! -nargs=* -range -bang {cmd} \
+                 call s:do_cmd({names}, '{cmd}', \
+                 \"<bang>\", <line1>, <line2>, <q-args>)\n",
                 cmd = c,
-                repo = p.name,
+                names = names,
             );
         }
+        if !p.load_commands.is_empty() {
+            plug_setup += "\n";
+        }
 
         if !p.for_types.is_empty() {
+            plug_setup += &format!("autocmd FileType {} {}\n\n", p.for_types.join(","), load);
+        }
+
+        for e in &p.on_event {
+            let (event, pattern) = split_event(e);
+            plug_setup += &format!("autocmd {} {} ++once {}\n\n", event, pattern, load);
+        }
+
+        if !p.on_func.is_empty() {
+            let patterns = p
+                .on_func
+                .iter()
+                .map(|f| func_pattern(f))
+                .collect::<Vec<_>>();
             plug_setup += &format!(
-                "autocmd FileType {} packadd {}\n\n",
-                p.for_types.join(","),
-                p.name,
+                "autocmd FuncUndefined {} ++once {}\n\n",
+                patterns.join(","),
+                load
+            );
+        }
+
+        if !p.on_path.is_empty() {
+            plug_setup += &format!(
+                "autocmd BufRead,BufNewFile {} ++once {} | call s:detect(expand('<afile>'))\n\n",
+                p.on_path.join(","),
+                load
+            );
+        }
+
+        for m in &p.on_map {
+            // the map as an argument of s:do_map, not translated as keys
+            let arg = m.replace('\'', "''").replace('<', "<lt>");
+            plug_setup += &format!(
+                "nnoremap <silent> {map} :<C-U>call <SID>do_map({names}, '{arg}', '')<CR>\n\
+                 xnoremap <silent> {map} :<C-U>call <SID>do_map({names}, '{arg}', 'gv')<CR>\n\
+                 inoremap <silent> {map} <C-O>:call <SID>do_map({names}, '{arg}', '')<CR>\n\n",
+                map = m,
+                names = names,
+                arg = arg,
             );
         }
 
         if !plug_setup.is_empty() {
-            plug_setup = format!("\" {}\n", &p.name) + &plug_setup;
-            f.write_all(plug_setup.as_bytes())?;
+            content += &format!("\" {}\n", &p.name);
+            content += &plug_setup;
 
             plug_setup.clear();
         }
     }
-    Ok(())
+    // only Neovim requires Lua modules from the runtime path
+    if !on_require.is_empty() {
+        content += &format!(
+            "\" Lua modules\nif has('nvim')\n\
+             let g:pac_load = get(function('s:load'), 'name')\n\
+             lua << EOF\n{}\n{}\n{}EOF\n\
+             unlet g:pac_load\nendif\n",
+            LUA_VIM_LOAD, LUA_REQUIRE_HOOK, on_require
+        );
+    }
+    Ok(content)
+}
+
+/// Lines registering the Lua modules of `pack` to load the packages `names`
+fn lua_on_require(pack: &Package, names: &[&str]) -> String {
+    let names = names.iter().map(|n| lua_string(n)).collect::<Vec<_>>();
+    pack.on_require
+        .iter()
+        .map(|m| {
+            format!(
+                "on_require[{}] = {{ {} }}\n",
+                lua_string(m),
+                names.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// A Lua autocommand running the Lua statements `action` once
+fn lua_autocmd(events: &[&str], patterns: &[String], action: &str) -> String {
+    let list = |items: Vec<String>| match items.as_slice() {
+        [item] => item.clone(),
+        _ => format!("{{ {} }}", items.join(", ")),
+    };
+    format!(
+        "vim.api.nvim_create_autocmd({}, {{\n    \
+         pattern = {},\n    \
+         once = true,\n    \
+         callback = function(args) {} end,\n\
+         }})\n\n",
+        list(events.iter().map(|e| lua_string(e)).collect()),
+        list(patterns.iter().map(|p| lua_string(p)).collect()),
+        action,
+    )
+}
+
+fn lua_loader(packs: &[Package]) -> Result<String> {
+    let mut content = format!("{}\n", PAC_LUA_PLUGIN_HEADER);
+    if packs.iter().any(|p| !p.on_require.is_empty()) {
+        content += &format!("{}\n", LUA_REQUIRE_HOOK);
+    }
+
+    let mut plug_setup = String::new();
+    for p in packs.iter() {
+        let load_names = load_names(p, packs)?;
+        if !p.on_require.is_empty() {
+            plug_setup += &format!("{}\n", lua_on_require(p, &load_names));
+        }
+        let names = load_names.iter().map(|n| lua_string(n)).collect::<Vec<_>>();
+        let names = format!("{{ {} }}", names.join(", "));
+        let load = format!("load({})", names);
+        if !p.load_commands.is_empty() || !p.on_map.is_empty() {
+            let list = |items: &[String]| {
+                items
+                    .iter()
+                    .map(|i| lua_string(i))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            plug_setup += &format!(
+                "stubs[{}] = {{ cmds = {{ {} }}, maps = {{ {} }} }}\n\n",
+                lua_string(&p.name),
+                list(&p.load_commands),
+                list(&p.on_map),
+            );
+        }
+        for c in &p.load_commands {
+            plug_setup += &format!(
+                "vim.api.nvim_create_user_command({cmd}, function(opts)\n    \
+                 do_cmd({names}, {cmd}, opts)\n\
+                 end, cmd_opts)\n\n",
+                cmd = lua_string(c),
+                names = names,
+            );
+        }
+
+        if !p.for_types.is_empty() {
+            plug_setup += &lua_autocmd(&["FileType"], &p.for_types, &load);
+        }
+
+        for e in &p.on_event {
+            let (event, pattern) = split_event(e);
+            plug_setup += &lua_autocmd(&[event], &[pattern.to_string()], &load);
+        }
+
+        if !p.on_func.is_empty() {
+            let patterns = p
+                .on_func
+                .iter()
+                .map(|f| func_pattern(f))
+                .collect::<Vec<_>>();
+            plug_setup += &lua_autocmd(&["FuncUndefined"], &patterns, &load);
+        }
+
+        if !p.on_path.is_empty() {
+            plug_setup += &lua_autocmd(
+                &["BufRead", "BufNewFile"],
+                &p.on_path,
+                &format!("{} detect(args.buf)", load),
+            );
+        }
+
+        for m in &p.on_map {
+            plug_setup += &format!(
+                "vim.keymap.set({{ 'n', 'x', 'i' }}, {map}, \
+                 function() do_map({names}, {map}) end)\n\n",
+                map = lua_string(m),
+                names = names,
+            );
+        }
+
+        if !plug_setup.is_empty() {
+            content += &format!("-- {}\n", &p.name);
+            content += &plug_setup;
+
+            plug_setup.clear();
+        }
+    }
+    Ok(content)
 }
 
 fn read_dir<H>(dir: &Path, mut action: H) -> Result<()>
@@ -361,24 +1443,27 @@ where
     if !dir.is_dir() {
         return Ok(());
     }
-    for entry in dir.read_dir()? {
-        if let Ok(e) = entry {
-            let sub = e.path();
-            let item = match sub.file_name().iter().flat_map(|s| s.to_str()).next() {
-                None => continue,
-                Some(i) => i.to_string(),
-            };
-            if sub.is_dir() && !item.starts_with('.') {
-                action(&sub, item)?;
-            }
+    for e in dir.read_dir()?.flatten() {
+        let sub = e.path();
+        let item = match sub.file_name().iter().flat_map(|s| s.to_str()).next() {
+            None => continue,
+            Some(i) => i.to_string(),
+        };
+        if sub.is_dir() && !item.starts_with('.') {
+            action(&sub, item)?;
         }
     }
     Ok(())
 }
 
-pub fn walk_packs<F>(category: &Option<String>, start: bool, opt: bool, callback: F) -> Result<()>
+pub fn walk_packs<F>(
+    category: &Option<String>,
+    start: bool,
+    opt: bool,
+    mut callback: F,
+) -> Result<()>
 where
-    F: Fn(&str, &str, &str),
+    F: FnMut(&str, &str, &str),
 {
     read_dir(&VIM_PACKAGE_DIR, |path, cate| {
         let is_match = category.as_ref().map_or(true, |c| *c == cate);
@@ -404,12 +1489,276 @@ where
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    /// An opt package `u/<name>` requiring the packages `u/<repo>` in
+    /// `requires` by their full remotes, whatever the host setting
+    pub(crate) fn pack(name: &str, requires: &[&str]) -> Package {
+        let mut p = Package::new(
+            name,
+            &format!("https://github.com/u/{}", name),
+            "default",
+            true,
+        );
+        p.requires = requires
+            .iter()
+            .map(|r| format!("https://github.com/{}", r))
+            .collect();
+        p
+    }
+
     #[test]
     fn package_idname_from_remote() {
         let remote = "https://github.com/username/repo";
         assert_eq!(Package::idname_from_remote(remote), "username/repo");
+        let remote = "https://git.sr.ht/~username/repo";
+        assert_eq!(
+            Package::idname_from_remote(remote),
+            "git.sr.ht/~username/repo"
+        );
+        let remote = "file:///home/username/repo";
+        assert_eq!(Package::idname_from_remote(remote), "username/repo");
+        let remote = "git@github.com:username/repo.git";
+        assert_eq!(Package::idname_from_remote(remote), "username/repo");
+        // existing plugins stay where they are
+        assert_eq!(Package::name_from_remote(remote), "repo.git");
+        let remote = "https://github.com/username/foo.vim.git";
+        assert_eq!(Package::idname_from_remote(remote), "username/foo.vim");
+        assert_eq!(Package::name_from_remote(remote), "foo.vim.git");
+        let remote = "ssh://git@gitlab.com/group/repo.git";
+        assert_eq!(Package::idname_from_remote(remote), "gitlab.com/group/repo");
+        // lock and snapshot entries written before
+        assert_eq!(migrate_idname("username/foo.vim.git"), "username/foo.vim");
+        assert_eq!(migrate_idname("username/foo.vim"), "username/foo.vim");
+    }
+
+    #[test]
+    fn settings_expand_remote() {
+        let mut settings = Settings::default();
+        settings
+            .aliases
+            .insert("work".to_string(), "https://git.example.com/".to_string());
+        assert_eq!(
+            settings.expand_remote("user/repo"),
+            "https://github.com/user/repo"
+        );
+        assert_eq!(
+            settings.expand_remote("gitlab:user/repo"),
+            "https://gitlab.com/user/repo"
+        );
+        assert_eq!(
+            settings.expand_remote("sr.ht:~user/repo"),
+            "https://git.sr.ht/~user/repo"
+        );
+        assert_eq!(
+            settings.expand_remote("work:team/repo"),
+            "https://git.example.com/team/repo"
+        );
+        assert_eq!(
+            settings.expand_remote("https://host/user/repo"),
+            "https://host/user/repo"
+        );
+        assert_eq!(
+            settings.expand_remote("git@host:user/repo"),
+            "git@host:user/repo"
+        );
+    }
+
+    #[test]
+    fn package_depth() {
+        let load = |depth: &str| {
+            let doc = format!("remote: https://github.com/u/foo\ndepth: {}", depth);
+            Package::from_yaml(&YamlLoader::load_from_str(&doc).unwrap()[0])
+        };
+        let pack = load("1").unwrap();
+        assert_eq!(pack.depth, Some(1));
+        assert_eq!(
+            Package::from_yaml(&pack.into_yaml()).unwrap().depth,
+            Some(1)
+        );
+        assert!(load("0").is_err());
+        assert!(load("4294967296").is_err());
+        assert!(load("full").is_err());
+
+        // the depth in settings only applies to packages without one
+        let settings = Settings {
+            depth: Some(3),
+            ..Settings::default()
+        };
+        assert_eq!(settings.clone_depth(&load("1").unwrap()), Some(1));
+        let pack = Package::new("foo", "https://github.com/u/foo", "default", false);
+        assert_eq!(settings.clone_depth(&pack), Some(3));
+        assert_eq!(Settings::default().clone_depth(&pack), None);
+    }
+
+    #[test]
+    fn resolve_idname_shorthands() {
+        let packs = vec![Package::new(
+            "repo",
+            "https://gitlab.com/user/repo",
+            "default",
+            false,
+        )];
+        let settings = Settings::default();
+        assert_eq!(
+            settings.resolve_idname(&packs, "gitlab.com/user/repo"),
+            "gitlab.com/user/repo"
+        );
+        assert_eq!(
+            settings.resolve_idname(&packs, "gitlab:user/repo"),
+            "gitlab.com/user/repo"
+        );
+        assert_eq!(settings.resolve_idname(&packs, "user/other"), "user/other");
+    }
+
+    #[test]
+    fn settings_from_yaml() {
+        let docs =
+            YamlLoader::load_from_str("threads: 4\nhost: https://gitlab.com\ndepth: 1").unwrap();
+        let settings = Settings::from_yaml(&docs[0]).unwrap();
+        assert_eq!(settings.threads, Some(4));
+        assert_eq!(settings.host, "https://gitlab.com/");
+        assert_eq!(settings.depth, Some(1));
+        assert_eq!(settings.category, "default");
+        assert_eq!(settings.build_timeout, None);
+
+        assert_eq!(settings.loader, Loader::Vim);
+
+        let docs = YamlLoader::load_from_str("threads: 0").unwrap();
+        assert!(Settings::from_yaml(&docs[0]).is_err());
+        let docs = YamlLoader::load_from_str("depth: 4294967296").unwrap();
+        assert!(Settings::from_yaml(&docs[0]).is_err());
+
+        let docs = YamlLoader::load_from_str("loader: lua").unwrap();
+        assert_eq!(Settings::from_yaml(&docs[0]).unwrap().loader, Loader::Lua);
+    }
+
+    #[test]
+    fn lua_loader_stubs() {
+        let mut pack = Package::new("vim-go", "https://github.com/fatih/vim-go", "default", true);
+        pack.load_commands = vec!["GoRun".to_string(), "GoBuild".to_string()];
+        pack.for_types = vec!["go".to_string()];
+        pack.on_event = vec!["User GoReady".to_string()];
+        pack.on_path = vec!["*.tmpl".to_string()];
+        pack.on_map = vec!["<Plug>(go-run)".to_string()];
+
+        let content = lua_loader(&[pack]).unwrap();
+        assert!(content.starts_with(PAC_LUA_PLUGIN_HEADER));
+        assert!(content.ends_with(
+            "-- vim-go\n\
+             stubs[\"vim-go\"] = { cmds = { \"GoRun\", \"GoBuild\" }, \
+             maps = { \"<Plug>(go-run)\" } }\n\n\
+             vim.api.nvim_create_user_command(\"GoRun\", function(opts)\n    \
+             do_cmd({ \"vim-go\" }, \"GoRun\", opts)\n\
+             end, cmd_opts)\n\n\
+             vim.api.nvim_create_user_command(\"GoBuild\", function(opts)\n    \
+             do_cmd({ \"vim-go\" }, \"GoBuild\", opts)\n\
+             end, cmd_opts)\n\n\
+             vim.api.nvim_create_autocmd(\"FileType\", {\n    \
+             pattern = \"go\",\n    \
+             once = true,\n    \
+             callback = function(args) load({ \"vim-go\" }) end,\n\
+             })\n\n\
+             vim.api.nvim_create_autocmd(\"User\", {\n    \
+             pattern = \"GoReady\",\n    \
+             once = true,\n    \
+             callback = function(args) load({ \"vim-go\" }) end,\n\
+             })\n\n\
+             vim.api.nvim_create_autocmd({ \"BufRead\", \"BufNewFile\" }, {\n    \
+             pattern = \"*.tmpl\",\n    \
+             once = true,\n    \
+             callback = function(args) load({ \"vim-go\" }) detect(args.buf) end,\n\
+             })\n\n\
+             vim.keymap.set({ 'n', 'x', 'i' }, \"<Plug>(go-run)\", \
+             function() do_map({ \"vim-go\" }, \"<Plug>(go-run)\") end)\n\n"
+        ));
+    }
+
+    #[test]
+    fn vim_loader_triggers() {
+        let mut pack = Package::new("fzf", "https://github.com/junegunn/fzf", "default", true);
+        pack.load_commands = vec!["FZF".to_string(), "Files".to_string()];
+        pack.on_event = vec!["InsertEnter".to_string()];
+        pack.on_func = vec!["fzf#".to_string()];
+        pack.on_path = vec!["*.fzf".to_string(), "*.fz".to_string()];
+        pack.on_map = vec!["<Leader>f".to_string()];
+
+        let content = vim_loader(&[pack]).unwrap();
+        assert!(content.ends_with(
+            "\" fzf\n\
+             let s:stubs['fzf'] = {'cmds': ['FZF', 'Files'], 'maps': ['<Leader>f']}\n\
+             command! -nargs=* -range -bang FZF call s:do_cmd(['fzf'], 'FZF', \
+             \"<bang>\", <line1>, <line2>, <q-args>)\n\
+             command! -nargs=* -range -bang Files call s:do_cmd(['fzf'], 'Files', \
+             \"<bang>\", <line1>, <line2>, <q-args>)\n\n\
+             autocmd InsertEnter * ++once call s:load(['fzf'])\n\n\
+             autocmd FuncUndefined fzf#* ++once call s:load(['fzf'])\n\n\
+             autocmd BufRead,BufNewFile *.fzf,*.fz ++once call s:load(['fzf']) \
+             | call s:detect(expand('<afile>'))\n\n\
+             nnoremap <silent> <Leader>f :<C-U>call <SID>do_map(['fzf'], '<lt>Leader>f', '')<CR>\n\
+             xnoremap <silent> <Leader>f \
+             :<C-U>call <SID>do_map(['fzf'], '<lt>Leader>f', 'gv')<CR>\n\
+             inoremap <silent> <Leader>f \
+             <C-O>:call <SID>do_map(['fzf'], '<lt>Leader>f', '')<CR>\n\n"
+        ));
+    }
+
+    #[test]
+    fn loaders_on_require() {
+        let mut pack = Package::new(
+            "telescope",
+            "https://github.com/u/telescope",
+            "default",
+            true,
+        );
+        pack.on_require = vec!["telescope".to_string()];
+
+        let content = lua_loader(&[pack.clone()]).unwrap();
+        assert!(content.contains(LUA_REQUIRE_HOOK));
+        assert!(
+            content.ends_with("-- telescope\non_require[\"telescope\"] = { \"telescope\" }\n\n")
+        );
+
+        let content = vim_loader(&[pack]).unwrap();
+        assert!(content.ends_with(&format!(
+            "\" Lua modules\n\
+             if has('nvim')\n\
+             let g:pac_load = get(function('s:load'), 'name')\n\
+             lua << EOF\n{}\n{}\n\
+             on_require[\"telescope\"] = {{ \"telescope\" }}\n\
+             EOF\n\
+             unlet g:pac_load\n\
+             endif\n",
+            LUA_VIM_LOAD, LUA_REQUIRE_HOOK
+        )));
+    }
+
+    #[test]
+    fn requirements_order_and_cycles() {
+        let mut packs = vec![
+            pack("cmp-buffer", &["u/nvim-cmp", "u/plenary"]),
+            pack("nvim-cmp", &["u/plenary", "u/missing"]),
+            pack("plenary", &[]),
+        ];
+        packs[2].opt = false;
+        let names = |p: Vec<&Package>| p.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            names(requirements(&packs[0], &packs).unwrap()),
+            vec!["plenary", "nvim-cmp"]
+        );
+        // start packages are loaded already
+        assert_eq!(
+            load_names(&packs[0], &packs).unwrap(),
+            vec!["nvim-cmp", "cmp-buffer"]
+        );
+
+        packs[2].requires = vec!["github:u/cmp-buffer".to_string()];
+        let err = requirements(&packs[0], &packs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Dependency cycle: u/cmp-buffer -> u/nvim-cmp -> u/plenary -> u/cmp-buffer"
+        );
+        assert!(check_requires(&packs).is_err());
     }
 }
@@ -0,0 +1,365 @@
+use crate::echo;
+use crate::package::Package;
+use crate::utils::Spinner;
+use crate::Error;
+
+use clap::ArgMatches;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use termion::{color, terminal_size};
+
+/// Stage of the task of a package
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Cloning,
+    Fetching,
+    CheckingOut,
+    Submodules,
+    Building,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Phase::Cloning => "cloning",
+            Phase::Fetching => "fetching",
+            Phase::CheckingOut => "checking out",
+            Phase::Submodules => "updating submodules",
+            Phase::Building => "building",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Progress of downloading objects from a remote, as reported by git
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transfer {
+    pub total_objects: usize,
+    pub received_objects: usize,
+    pub indexed_objects: usize,
+    pub received_bytes: usize,
+    pub total_deltas: usize,
+    pub indexed_deltas: usize,
+}
+
+impl Transfer {
+    /// Stage of the transfer and its completion in percent, used to avoid
+    /// reporting every single object
+    fn stage(&self) -> (u8, usize) {
+        let percent = |n: usize, total: usize| (n * 100).checked_div(total).unwrap_or(100);
+        if self.received_objects < self.total_objects {
+            (0, percent(self.received_objects, self.total_objects))
+        } else if self.indexed_objects < self.total_objects {
+            (1, percent(self.indexed_objects, self.total_objects))
+        } else {
+            (2, percent(self.indexed_deltas, self.total_deltas))
+        }
+    }
+}
+
+impl<'a> From<git2::Progress<'a>> for Transfer {
+    fn from(p: git2::Progress<'a>) -> Transfer {
+        Transfer {
+            total_objects: p.total_objects(),
+            received_objects: p.received_objects(),
+            indexed_objects: p.indexed_objects(),
+            received_bytes: p.received_bytes(),
+            total_deltas: p.total_deltas(),
+            indexed_deltas: p.indexed_deltas(),
+        }
+    }
+}
+
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (stage, percent) = self.stage();
+        match stage {
+            0 => write!(
+                f,
+                "receiving objects {}% ({}/{}), {}",
+                percent,
+                self.received_objects,
+                self.total_objects,
+                human_bytes(self.received_bytes)
+            ),
+            1 => write!(
+                f,
+                "indexing objects {}% ({}/{})",
+                percent, self.indexed_objects, self.total_objects
+            ),
+            _ => write!(
+                f,
+                "resolving deltas {}% ({}/{})",
+                percent, self.indexed_deltas, self.total_deltas
+            ),
+        }
+    }
+}
+
+/// Receives the progress of the tasks run by a `TaskManager`. Methods are
+/// called concurrently from the worker threads.
+pub trait Reporter: Send + Sync {
+    /// Called once before any task is started
+    fn begin(&self) {}
+    fn started(&self, pack: &Package);
+    fn phase(&self, pack: &Package, phase: Phase);
+    /// Objects received from the remote while fetching
+    fn transfer(&self, _pack: &Package, _transfer: &Transfer) {}
+    /// Progress messages sent by the remote, e.g. "Counting objects: 10%"
+    fn remote_message(&self, _pack: &Package, _msg: &str) {}
+    fn finished(&self, pack: &Package);
+    fn failed(&self, pack: &Package, err: &Error);
+    /// The task was not run for the package, e.g. for local plugins
+    fn skipped(&self, pack: &Package, _reason: &Error) {
+        self.finished(pack);
+    }
+    /// Called once after all tasks are done
+    fn end(&self) {}
+}
+
+/// A `Reporter` bound to a single package, handed to task functions
+pub struct PackReporter<'a> {
+    reporter: &'a dyn Reporter,
+    pack: &'a Package,
+    last_transfer: Cell<Option<(u8, usize)>>,
+}
+
+impl<'a> PackReporter<'a> {
+    pub fn new(reporter: &'a dyn Reporter, pack: &'a Package) -> PackReporter<'a> {
+        PackReporter {
+            reporter,
+            pack,
+            last_transfer: Cell::new(None),
+        }
+    }
+
+    pub fn phase(&self, phase: Phase) {
+        self.reporter.phase(self.pack, phase);
+    }
+
+    /// Report the transfer, only if its stage or percentage changed.
+    pub fn transfer(&self, transfer: &Transfer) {
+        let stage = Some(transfer.stage());
+        if self.last_transfer.replace(stage) != stage {
+            self.reporter.transfer(self.pack, transfer);
+        }
+    }
+
+    pub fn remote_message(&self, data: &[u8]) {
+        let data = String::from_utf8_lossy(data);
+        // messages are updated in place with carriage returns
+        if let Some(msg) = data.split(['\r', '\n']).rfind(|m| !m.trim().is_empty()) {
+            self.reporter.remote_message(self.pack, msg.trim());
+        }
+    }
+}
+
+/// How the progress of tasks is shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    /// One line per package updated in place, needs a terminal
+    Terminal,
+    /// One line per event, suitable for logs and CI
+    Plain,
+    Quiet,
+}
+
+impl Progress {
+    /// Parse the global `--progress` and `--json` args. Falls back to plain
+    /// output when stdout is not a terminal.
+    pub fn from_matches(m: &ArgMatches) -> Progress {
+        if m.is_present("json") {
+            return Progress::Quiet;
+        }
+        match m.value_of("progress") {
+            Some("terminal") => Progress::Terminal,
+            Some("plain") => Progress::Plain,
+            _ if termion::is_tty(&io::stdout()) => Progress::Terminal,
+            _ => Progress::Plain,
+        }
+    }
+
+    pub fn reporter(self) -> Box<dyn Reporter> {
+        match self {
+            Progress::Terminal => Box::new(TerminalReporter::default()),
+            Progress::Plain => Box::new(PlainReporter),
+            Progress::Quiet => Box::new(QuietReporter),
+        }
+    }
+}
+
+const MSG_MARGIN: u16 = 5;
+const SIGN_MARGIN: u16 = 3;
+
+/// Line of the terminal showing the progress of a package
+struct Row {
+    line: u16,
+    /// Offset of the message after the package name
+    pos: u16,
+    spinner: Option<Spinner>,
+}
+
+/// Shows one line per package with a spinner, updated in place.
+#[derive(Default)]
+pub struct TerminalReporter {
+    rows: Mutex<HashMap<String, Row>>,
+}
+
+impl TerminalReporter {
+    fn message(&self, pack: &Package, msg: &str) {
+        let rows = self.rows.lock().unwrap();
+        if let Some(row) = rows.get(&pack.idname) {
+            echo::inline_message(row.line, MSG_MARGIN + row.pos, msg);
+        }
+    }
+
+    /// Stop the spinner of a package and return its line and message offset
+    fn stop(&self, pack: &Package) -> Option<(u16, u16)> {
+        let mut rows = self.rows.lock().unwrap();
+        let row = rows.get_mut(&pack.idname)?;
+        if let Some(spinner) = row.spinner.take() {
+            spinner.stop();
+        }
+        Some((row.line, row.pos))
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn begin(&self) {
+        let y = match terminal_size() {
+            Err(e) => die!("Fail to get terminal size. {}", e),
+            Ok((_, y)) => y,
+        };
+
+        if y <= 2 {
+            die!("Terminal size too small.");
+        }
+        println!();
+    }
+
+    fn started(&self, pack: &Package) {
+        let line = echo::line();
+        let msg = format!(" [{}]", &pack.idname);
+        let pos = msg.len() as u16;
+        echo::message(line, 0, &format!("    {} syncing", &msg));
+
+        let row = Row {
+            line,
+            pos,
+            spinner: Some(Spinner::spin(line, SIGN_MARGIN)),
+        };
+        self.rows.lock().unwrap().insert(pack.idname.clone(), row);
+    }
+
+    fn phase(&self, pack: &Package, phase: Phase) {
+        self.message(pack, &phase.to_string());
+    }
+
+    fn transfer(&self, pack: &Package, transfer: &Transfer) {
+        self.message(pack, &transfer.to_string());
+    }
+
+    fn remote_message(&self, pack: &Package, msg: &str) {
+        self.message(pack, &msg.to_lowercase());
+    }
+
+    fn finished(&self, pack: &Package) {
+        if let Some((line, pos)) = self.stop(pack) {
+            echo::character(line, SIGN_MARGIN, '✓', color::Green);
+            echo::inline_message(line, MSG_MARGIN + pos, "done");
+        }
+    }
+
+    fn failed(&self, pack: &Package, err: &Error) {
+        if let Some((line, pos)) = self.stop(pack) {
+            echo::character(line, SIGN_MARGIN, '✗', color::Red);
+            echo::inline_message(line, MSG_MARGIN + pos, &format!("{}", err));
+        }
+    }
+
+    fn skipped(&self, pack: &Package, reason: &Error) {
+        if let Some((line, pos)) = self.stop(pack) {
+            echo::character(line, SIGN_MARGIN, '-', color::Yellow);
+            echo::inline_message(line, MSG_MARGIN + pos, &format!("{}", reason));
+        }
+    }
+
+    fn end(&self) {
+        println!();
+    }
+}
+
+/// Prints one line per event.
+pub struct PlainReporter;
+
+impl Reporter for PlainReporter {
+    fn started(&self, pack: &Package) {
+        println!("[{}] syncing", &pack.idname);
+    }
+
+    fn phase(&self, pack: &Package, phase: Phase) {
+        println!("[{}] {}", &pack.idname, phase);
+    }
+
+    fn finished(&self, pack: &Package) {
+        println!("[{}] ✓ done", &pack.idname);
+    }
+
+    fn failed(&self, pack: &Package, err: &Error) {
+        println!("[{}] ✗ {}", &pack.idname, err);
+    }
+
+    fn skipped(&self, pack: &Package, reason: &Error) {
+        println!("[{}] - {}", &pack.idname, reason);
+    }
+}
+
+/// Shows nothing.
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn started(&self, _: &Package) {}
+    fn phase(&self, _: &Package, _: Phase) {}
+    fn finished(&self, _: &Package) {}
+    fn failed(&self, _: &Package, _: &Error) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_display() {
+        let mut t = Transfer {
+            total_objects: 200,
+            received_objects: 50,
+            indexed_objects: 40,
+            received_bytes: 3 * 1024 * 1024 / 2,
+            total_deltas: 0,
+            indexed_deltas: 0,
+        };
+        assert_eq!(t.to_string(), "receiving objects 25% (50/200), 1.50 MiB");
+        t.received_objects = 200;
+        assert_eq!(t.to_string(), "indexing objects 20% (40/200)");
+        t.indexed_objects = 200;
+        assert_eq!(t.to_string(), "resolving deltas 100% (0/0)");
+    }
+}
@@ -1,6 +1,7 @@
-use crate::echo;
-use crate::package::Package;
-use crate::utils::Spinner;
+use crate::git::HeadChange;
+use crate::json::Json;
+use crate::package::{self, Package};
+use crate::report::{PackReporter, Phase, Progress, Reporter, TerminalReporter};
 use crate::Error;
 use crate::Result;
 
@@ -12,17 +13,71 @@ use std::io;
 use std::process;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use termion::{color, terminal_size};
 
+#[derive(Clone, Copy)]
 pub enum TaskType {
     Install,
     Update,
+    /// Only fetch from remote, so there is nothing to build
+    Check,
+}
+
+/// Results of running the tasks of a `TaskManager`
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// idnames of packages whose task failed
+    pub failures: Vec<String>,
+    /// HEAD changes of packages synced successfully, keyed by idname
+    pub changes: Vec<(String, HeadChange)>,
+    /// Error messages of packages, keyed by idname. A package may have both
+    /// an error and a change if it was synced but failed to build.
+    pub errors: Vec<(String, String)>,
+}
+
+impl Outcome {
+    /// One record per package with its HEAD change and error, if any.
+    pub fn to_json(&self) -> Json {
+        let mut idnames = self
+            .changes
+            .iter()
+            .map(|(i, _)| i)
+            .chain(self.errors.iter().map(|(i, _)| i))
+            .collect::<Vec<_>>();
+        idnames.sort();
+        idnames.dedup();
+
+        let records = idnames
+            .into_iter()
+            .map(|idname| {
+                let change = self.changes.iter().find(|(i, _)| i == idname).map(|c| c.1);
+                let error = self
+                    .errors
+                    .iter()
+                    .find(|(i, _)| i == idname)
+                    .map(|e| e.1.clone());
+                Json::object(vec![
+                    ("idname", Json::from(idname.as_str())),
+                    ("success", Json::from(error.is_none())),
+                    ("error", Json::from(error)),
+                    (
+                        "old",
+                        Json::from(change.and_then(|c| c.old).map(|o| o.to_string())),
+                    ),
+                    ("new", Json::from(change.map(|c| c.new.to_string()))),
+                ])
+            })
+            .collect();
+        Json::Array(records)
+    }
 }
 
 pub struct TaskManager {
     task_type: TaskType,
     packs: Vec<Package>,
     thread_num: usize,
+    reporter: Arc<dyn Reporter>,
+    /// Report errors as JSON
+    json: bool,
 }
 
 impl TaskManager {
@@ -31,72 +86,82 @@ impl TaskManager {
             task_type,
             packs: Vec::new(),
             thread_num,
+            reporter: Arc::new(TerminalReporter::default()),
+            json: false,
         }
     }
 
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.set_reporter(progress.reporter());
+    }
+
+    pub fn set_json(&mut self, json: bool) {
+        self.json = json;
+    }
+
+    pub fn set_reporter(&mut self, reporter: Box<dyn Reporter>) {
+        self.reporter = reporter.into();
+    }
+
     pub fn add(&mut self, pack: Package) {
         self.packs.push(pack);
     }
 
-    /// returns true on success otherwise false
-    fn update<F>(pack: &Package, line: u16, func: F) -> bool
+    /// Modify the queued packages in place before running the tasks.
+    pub fn map<F>(&mut self, func: F)
     where
-        F: Fn(&Package) -> (Result<()>, bool),
+        F: FnMut(&mut Package),
     {
-        let msg = format!(" [{}]", &pack.idname);
-        let pos = msg.len() as u16;
-        echo::message(line, 0, &format!("    {} syncing", &msg));
-
-        const MSG_MARGIN: u16 = 5;
-        const SIGN_MARGIN: u16 = 3;
-
-        macro_rules! print_err {
-            ($err:expr) => {
-                let msg = format!("{}", $err);
-                echo::character(line, SIGN_MARGIN, '✗', color::Red);
-                echo::inline_message(line, MSG_MARGIN + pos, &msg);
-            };
-        }
+        self.packs.iter_mut().for_each(func);
+    }
 
-        let mut successful = true;
-        let spinner = Spinner::spin(line, SIGN_MARGIN);
-        if let (Err(e), status) = func(pack) {
-            spinner.stop();
-            print_err!(e);
-            successful = status;
-        } else {
-            if pack.build_command.is_some() {
-                echo::inline_message(line, MSG_MARGIN + pos, "building");
-                if let Err(e) = pack.try_build().map_err(|e| Error::build(format!("{}", e))) {
-                    print_err!(e);
-                }
+    /// Run the task and build the package, reporting the progress. Returns
+    /// true on success otherwise false, along with the HEAD change if the
+    /// package was synced and the error if any.
+    fn sync<F>(
+        task_type: TaskType,
+        pack: &Package,
+        reporter: &dyn Reporter,
+        func: F,
+    ) -> (bool, Option<HeadChange>, Option<Error>)
+    where
+        F: Fn(&Package, &PackReporter) -> (Result<HeadChange>, bool),
+    {
+        reporter.started(pack);
+        let (successful, change, err) = match func(pack, &PackReporter::new(reporter, pack)) {
+            (Err(e @ Error::SkipLocal), _) | (Err(e @ Error::SkipPinned), _) => {
+                reporter.skipped(pack, &e);
+                return (true, None, None);
             }
-
-            spinner.stop();
-            if successful {
-                echo::character(line, SIGN_MARGIN, '✓', color::Green);
-                echo::inline_message(line, MSG_MARGIN + pos, "done");
+            (Err(e), status) => (status, None, Some(e)),
+            (Ok(c), _) => {
+                let mut err = None;
+                if pack.build_command.is_some() && !matches!(task_type, TaskType::Check) {
+                    reporter.phase(pack, Phase::Building);
+                    err = pack
+                        .try_build()
+                        .map_err(|e| Error::build(format!("{}", e)))
+                        .err();
+                }
+                (true, Some(c), err)
             }
+        };
+        match err {
+            Some(ref e) => reporter.failed(pack, e),
+            None => reporter.finished(pack),
         }
-        successful
+        (successful, change, err)
     }
 
-    pub fn run<F>(self, func: F) -> Vec<String>
+    pub fn run<F>(self, func: F) -> Outcome
     where
-        F: Fn(&Package) -> (Result<()>, bool) + Send + 'static + Copy,
+        F: Fn(&Package, &PackReporter) -> (Result<HeadChange>, bool) + Send + 'static + Copy,
     {
         if self.packs.is_empty() {
-            die!("No plugins to sync");
+            die_json!(self.json, "No plugins to sync");
         }
 
-        let y = match terminal_size() {
-            Err(e) => die!("Fail to get terminal size. {}", e),
-            Ok((_, y)) => y,
-        };
-
-        if y <= 2 {
-            die!("Terminal size too small.");
-        }
+        self.reporter.begin();
 
         let quit_notifier = match setup_signal() {
             Err(e) => die!("Fail to set up signal. {}", e),
@@ -104,38 +169,49 @@ impl TaskManager {
         };
 
         let threads = self.thread_num;
+        let task_type = self.task_type;
 
         let wg = WaitGroup::new();
         let (tx, rx) = bounded::<Option<Package>>(threads);
 
-        let failures = Arc::new(Mutex::new(vec![]));
+        let outcome = Arc::new(Mutex::new(Outcome::default()));
         let pending = Arc::new(Mutex::new(vec![]));
 
         for _ in 0..threads {
             let rx = rx.clone();
-            let failures = failures.clone();
+            let outcome = outcome.clone();
             let pending = pending.clone();
             let wg = wg.clone();
             let quit_notifier = quit_notifier.clone();
+            let reporter = self.reporter.clone();
             thread::spawn(move || {
                 while let Ok(Some(pack)) = rx.recv() {
                     log::info!("pack {}", &pack.idname);
                     let _wg = wg.clone();
-                    {
+                    // only fresh clones are cleaned up when interrupted
+                    if !pack.is_installed() {
                         let mut p = pending.lock().unwrap();
                         log::info!("add to pending:{}", &pack.idname);
                         p.push(pack.clone());
                     }
 
                     let name = pack.idname.clone();
-                    let failures = failures.clone();
+                    let outcome = outcome.clone();
+                    let reporter = reporter.clone();
 
                     let (wtx, wrx) = bounded(0);
                     thread::spawn(move || {
-                        let index = echo::line();
-                        if !Self::update(&pack, index, func) {
-                            let mut f = failures.lock().unwrap();
-                            f.push(pack.idname);
+                        let (successful, change, err) =
+                            Self::sync(task_type, &pack, &*reporter, func);
+                        let mut o = outcome.lock().unwrap();
+                        if !successful {
+                            o.failures.push(pack.idname.clone());
+                        }
+                        if let Some(e) = err {
+                            o.errors.push((pack.idname.clone(), e.to_string()));
+                        }
+                        if let Some(c) = change {
+                            o.changes.push((pack.idname, c));
                         }
                         let _ = wtx.send(());
                     });
@@ -154,10 +230,6 @@ impl TaskManager {
                 }
             });
         }
-        if !self.packs.is_empty() {
-            println!();
-        }
-
         for pack in self.packs.iter() {
             let _ = tx.send(Some(pack.clone()));
         }
@@ -167,13 +239,13 @@ impl TaskManager {
         }
         wg.wait();
 
-        if !self.packs.is_empty() {
-            println!();
-        }
+        self.reporter.end();
 
         log::info!("quit");
 
-        helptags();
+        if !matches!(self.task_type, TaskType::Check) {
+            helptags();
+        }
 
         if let TaskType::Install = self.task_type {
             for p in pending.lock().unwrap().iter() {
@@ -182,8 +254,8 @@ impl TaskManager {
             }
         }
 
-        let failures = failures.lock().unwrap();
-        failures.clone()
+        let mut outcome = outcome.lock().unwrap();
+        std::mem::take(&mut *outcome)
     }
 }
 
@@ -192,8 +264,10 @@ fn helptags() {
         .arg("--not-a-term")
         .arg("-c")
         .arg("silent! helptags ALL")
+        .arg("-c")
+        .arg("qa!")
         .stdout(process::Stdio::null())
-        .spawn()
+        .status()
     {
         Ok(_) => (),
         Err(e) => {
@@ -202,8 +276,10 @@ fn helptags() {
                     .arg("--headless")
                     .arg("-c")
                     .arg("silent! helptags ALL")
+                    .arg("-c")
+                    .arg("qa!")
                     .stdout(process::Stdio::null())
-                    .spawn()
+                    .status()
                     .expect("Error opening nvim");
             } else {
                 panic!("Somthing happened when calling vim!")
@@ -214,13 +290,103 @@ fn helptags() {
 
 fn setup_signal() -> io::Result<Receiver<()>> {
     let (s, r) = bounded(10);
-    let signals = Signals::new(&[signal_hook::SIGTERM, signal_hook::SIGINT])?;
+    let signals = Signals::new([signal_hook::SIGTERM, signal_hook::SIGINT])?;
 
     thread::spawn(move || {
-        for _ in signals.forever() {
+        if let Some(signal) = signals.forever().next() {
+            package::signal_builds(signal);
             drop(s);
-            return;
         }
     });
     Ok(r)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Oid;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Reporter for Arc<Recorder> {
+        fn started(&self, pack: &Package) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("started {}", pack.idname));
+        }
+        fn phase(&self, pack: &Package, phase: Phase) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", phase, pack.idname));
+        }
+        fn finished(&self, pack: &Package) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("finished {}", pack.idname));
+        }
+        fn failed(&self, pack: &Package, err: &Error) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("failed {}: {}", pack.idname, err));
+        }
+    }
+
+    fn check(pack: &Package, progress: &PackReporter) -> (Result<HeadChange>, bool) {
+        progress.phase(Phase::Fetching);
+        if pack.idname == "user/bad" {
+            return (Err(Error::NoPlugin), false);
+        }
+        let new = Oid::from_str("1234567").unwrap();
+        (
+            Ok(HeadChange {
+                old: Some(new),
+                new,
+            }),
+            true,
+        )
+    }
+
+    #[test]
+    fn task_manager_reports_progress() {
+        let recorder = Arc::new(Recorder::default());
+        let mut manager = TaskManager::new(TaskType::Check, 1);
+        manager.set_reporter(Box::new(recorder.clone()));
+        manager.add(Package::new(
+            "good",
+            "https://github.com/user/good",
+            "default",
+            false,
+        ));
+        manager.add(Package::new(
+            "bad",
+            "https://github.com/user/bad",
+            "default",
+            false,
+        ));
+
+        let outcome = manager.run(check);
+        assert_eq!(outcome.failures, vec!["user/bad"]);
+        assert_eq!(outcome.changes.len(), 1);
+        assert_eq!(outcome.changes[0].0, "user/good");
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                "started user/good",
+                "fetching user/good",
+                "finished user/good",
+                "started user/bad",
+                "fetching user/bad",
+                "failed user/bad: Can not find such plugin",
+            ]
+        );
+    }
+}
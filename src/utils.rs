@@ -18,6 +18,21 @@ macro_rules! die {
     })
 }
 
+/// Like `die!`, but prints `{"error": <message>}` to stdout instead if `json`
+/// is true, so that the output of `--json` stays machine readable.
+macro_rules! die_json {
+    ($json:expr, $($arg:tt)*) => ({
+        if $json {
+            use crate::json::Json;
+            let msg = format!($($arg)*);
+            println!("{}", Json::object(vec![("error", Json::from(msg))]));
+            ::std::process::exit(1)
+        } else {
+            die!($($arg)*)
+        }
+    })
+}
+
 pub struct Spinner {
     tx: Sender<bool>,
     handle: thread::JoinHandle<()>,
@@ -60,3 +75,8 @@ pub fn copy_directory<P: AsRef<Path>>(src: P, dst: P) -> Result<()> {
     }
     Ok(())
 }
+
+/// Quote a string as a Lua string literal
+pub fn lua_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}